@@ -1,17 +1,23 @@
+use std::ffi::CString;
 use std::io;
+use std::ops::Deref;
+use std::os::unix::ffi::OsStrExt;
 
-use libc::{c_int, ftok, semctl, semget, semop, IPC_CREAT, IPC_EXCL, IPC_PRIVATE, O_RDWR};
-// use libc::{SETVAL, SETALL}; Can't find them for some reason
-const SETVAL: c_int = 8;
-const SETALL: c_int = 9;
+use libc::{
+    ftok, key_t, sembuf, semctl, semget, semop, EINTR, IPC_CREAT, IPC_EXCL, IPC_RMID, SEM_UNDO,
+    SETVAL,
+};
 
 use memmap::{MmapMut, MmapOptions};
-use nix::unistd::{fork, ForkResult, Pid};
+use nix::unistd::{fork, getpid, ForkResult, Pid};
 
 struct Semaphore<T> {
     users: usize,
     id: i32,
     data: T,
+    /// Process that created the set and is responsible for removing it. `None` for handles
+    /// attached with `from_id`.
+    creator: Option<Pid>,
 }
 
 #[must_use = "if unused the Semaphore will immediately unlock"]
@@ -19,34 +25,233 @@ struct SemaphoreGuard<'a, T> {
     lock: &'a Semaphore<T>,
 }
 
-impl<T> Semaphore<T> {
-    pub fn new(users: usize, data: T) -> Self {
-        const FAILED_TO_OPEN_SEM_SET: i32 = -1;
-        let path: *const str = std::env::current_exe().unwrap().to_str().unwrap();
+/// Key of the semaphore set for `proj_id`, derived from the current executable
+fn key(proj_id: i32) -> io::Result<key_t> {
+    let path = std::env::current_exe()?;
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let key = unsafe { ftok(path.as_ptr(), proj_id) };
+    if key == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(key)
+}
+
+/// Adds `op` to the first semaphore of the set, blocking while that would make it negative.
+/// With `SEM_UNDO` the kernel reverts the change if the process dies while holding the lock.
+fn sem_add(id: i32, op: i16) -> io::Result<()> {
+    let mut buf = sembuf {
+        sem_num: 0,
+        sem_op: op,
+        sem_flg: SEM_UNDO as i16,
+    };
+    loop {
+        if unsafe { semop(id, &mut buf, 1) } == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(EINTR) {
+            return Err(err);
+        }
+    }
+}
 
+impl<T> Semaphore<T> {
+    /// Creates a new semaphore set with a key derived from the current executable and
+    /// `proj_id` (of which `ftok` only uses the lowest 8 bits).
+    ///
+    /// Fails with `ErrorKind::AlreadyExists` if a set with the same key exists, e.g. because
+    /// another instance of the program is running. Retry with a different `proj_id` then.
+    pub fn new(users: usize, data: T, proj_id: i32) -> io::Result<Self> {
+        let key = key(proj_id)?;
         unsafe {
-            let key = ftok(path.cast(), 1);
-            let res = semget(key, users as i32, IPC_PRIVATE); // try to get semaphore from existing set, this should fail
-            let id = if res == FAILED_TO_OPEN_SEM_SET {
-                semget(key, users as i32, IPC_CREAT | IPC_EXCL | O_RDWR)
-            // ToDo: Check error of semget
-            } else {
-                panic!("Semaphore at key {} already exists!", key)
-            };
-            if semctl(dbg!(id), 0, SETVAL, 1) == -1 {
-                panic!("Failed to clear Semaphore");
+            // IPC_EXCL makes creation fail if a set with this key already exists
+            let id = semget(key, users as i32, IPC_CREAT | IPC_EXCL | 0o600);
+            if id == -1 {
+                return Err(io::Error::last_os_error());
             }
-            Semaphore { users, id, data }
+            if semctl(id, 0, SETVAL, 1) == -1 {
+                let err = io::Error::last_os_error();
+                semctl(id, 0, IPC_RMID);
+                return Err(err);
+            }
+            Ok(Semaphore {
+                users,
+                id,
+                data,
+                creator: Some(getpid()),
+            })
+        }
+    }
+
+    /// Attaches to the set that `new` created with the same `proj_id`, e.g. in a process
+    /// started separately from the creator.
+    ///
+    /// Fails with `ErrorKind::NotFound` if there is no such set.
+    pub fn from_id(users: usize, data: T, proj_id: i32) -> io::Result<Self> {
+        let key = key(proj_id)?;
+        let id = unsafe { semget(key, users as i32, 0o600) };
+        if id == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Semaphore {
+            users,
+            id,
+            data,
+            creator: None,
+        })
+    }
+
+    /// Blocks until the lock is free and acquires it. It's released again when the returned
+    /// guard is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the semaphore set was removed in the meantime.
+    pub fn lock(&self) -> SemaphoreGuard<'_, T> {
+        if let Err(e) = sem_add(self.id, -1) {
+            panic!("failed to lock semaphore {}: {}", self.id, e);
+        }
+        SemaphoreGuard { lock: self }
+    }
+}
+
+impl<T> Drop for Semaphore<T> {
+    /// Removes the set if this is the handle it was created with. Forked children inherit a
+    /// copy of that handle, which is why the creating process is compared as well.
+    fn drop(&mut self) {
+        if self.creator == Some(getpid()) {
+            unsafe { semctl(self.id, 0, IPC_RMID) };
         }
     }
+}
 
-    pub fn from_id() -> Self {
-        //semget
-        unimplemented!()
+impl<T> Deref for SemaphoreGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.lock.data
     }
+}
 
-    pub fn lock<'a>(&'a self) -> SemaphoreGuard<'a, T> {
-        //semop()
-        unimplemented!()
+impl<T> Drop for SemaphoreGuard<'_, T> {
+    fn drop(&mut self) {
+        // can only fail if the set is already gone, and then there's nothing left to unlock
+        let _ = sem_add(self.lock.id, 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::mem::size_of;
+
+    use nix::sys::wait::waitpid;
+
+    #[test]
+    fn distinct_proj_ids_dont_collide() {
+        let first = Semaphore::new(1, (), 11).unwrap();
+        let second = Semaphore::new(1, (), 12).unwrap();
+        assert_ne!(first.id, second.id);
+
+        let collision = Semaphore::new(1, (), 11);
+        assert_eq!(
+            collision.err().map(|e| e.kind()),
+            Some(io::ErrorKind::AlreadyExists)
+        );
+    }
+
+    #[test]
+    fn from_id_attaches_to_existing_set() {
+        assert_eq!(
+            Semaphore::from_id(1, (), 13).err().map(|e| e.kind()),
+            Some(io::ErrorKind::NotFound)
+        );
+        let created = Semaphore::new(1, (), 13).unwrap();
+        let attached = Semaphore::from_id(1, (), 13).unwrap();
+        assert_eq!(created.id, attached.id);
+
+        // only the creator removes the set
+        drop(attached);
+        assert!(Semaphore::from_id(1, (), 13).is_ok());
+        drop(created);
+        assert!(Semaphore::from_id(1, (), 13).is_err());
+    }
+
+    /// Maximum number of semaphore sets on the system, SEMMNI
+    #[cfg(target_os = "linux")]
+    fn max_sets() -> usize {
+        // the 4th field
+        std::fs::read_to_string("/proc/sys/kernel/sem")
+            .ok()
+            .and_then(|s| s.split_whitespace().nth(3)?.parse::<usize>().ok())
+            .unwrap_or(32000)
+    }
+
+    /// Maximum number of semaphore sets on the system, SEMMNI
+    #[cfg(target_os = "macos")]
+    fn max_sets() -> usize {
+        let name = CString::new("kern.sysv.semmni").unwrap();
+        let mut value: libc::c_int = 0;
+        let mut len = size_of::<libc::c_int>();
+        let ret = unsafe {
+            libc::sysctlbyname(
+                name.as_ptr(),
+                &mut value as *mut libc::c_int as *mut libc::c_void,
+                &mut len,
+                std::ptr::null_mut(),
+                0,
+            )
+        };
+        assert_eq!(ret, 0, "{}", io::Error::last_os_error());
+        value as usize
+    }
+
+    // only where the limit can be looked up, guessing it could mean tens of thousands of sets
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    fn drop_removes_set() {
+        // more sets than the system allows at once, so this fails if dropping leaks them
+        for _ in 0..max_sets() + 100 {
+            Semaphore::new(1, (), 15).unwrap();
+        }
+    }
+
+    #[test]
+    fn lock_excludes_other_processes() {
+        const CHILDREN: usize = 2;
+        const ITERATIONS: usize = 5000;
+
+        let semaphore = Semaphore::new(1, (), 14).unwrap();
+        let mut mmap = MmapOptions::new()
+            .len(size_of::<usize>())
+            .map_anon()
+            .unwrap();
+        let counter = mmap.as_mut_ptr() as *mut usize;
+
+        let mut children = Vec::new();
+        for _ in 0..CHILDREN {
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => children.push(child),
+                Ok(ForkResult::Child) => {
+                    for _ in 0..ITERATIONS {
+                        let _guard = semaphore.lock();
+                        // deliberately not atomic, only the lock prevents lost increments
+                        unsafe {
+                            let value = counter.read_volatile();
+                            std::thread::yield_now();
+                            counter.write_volatile(value + 1);
+                        }
+                    }
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
+        for child in children {
+            waitpid(child, None).unwrap();
+        }
+        assert_eq!(unsafe { counter.read_volatile() }, CHILDREN * ITERATIONS);
     }
 }
@@ -1,5 +1,3 @@
-use lib;
-
 fn main() {
     println!("Hello, world!");
 }
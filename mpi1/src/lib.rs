@@ -1,23 +1,23 @@
 #![allow(dead_code)]
 use std::io::{Read, Result, Write};
 use std::str;
+use std::sync::atomic::{AtomicU8, Ordering};
 use std::time::Instant;
 
 use memmap::{MmapMut, MmapOptions};
+use nix::sys::wait::waitpid;
 use nix::unistd::{fork, ForkResult, Pid};
-use sysinfo::{ProcessExt, System, SystemExt};
 
 const IMAX: usize = 100000;
 const LMAX: usize = 1024 * 256;
 
 fn wait_for_process(pid: Pid) {
-    let mut sys = System::new();
-    sys.refresh_all();
-    if let Some(p) = sys.get_process(i32::from(pid)) {
-        while p.status().to_string() != "Zombie" {}
-    }
+    let _ = waitpid(pid, None);
 }
 
+/// Length of the owner byte behind the payload
+const OWNER_LEN: usize = 1;
+
 struct TransferBuffer {
     mmap: MmapMut,
 }
@@ -26,7 +26,7 @@ impl TransferBuffer {
     pub fn new(size: usize, owner: u8) -> Result<Self> {
         let mut mmap_options = MmapOptions::new();
         mmap_options
-            .len(size + 1)
+            .len(size + OWNER_LEN)
             .map_anon()
             .map(|mmap| TransferBuffer { mmap })
             .map(|mut buf| {
@@ -35,35 +35,33 @@ impl TransferBuffer {
             })
     }
 
-    fn owner(&self) -> *const u8 {
-        &self.mmap[self.size()]
+    /// The owner byte, an `AtomicU8` has the same layout
+    fn owner(&self) -> &AtomicU8 {
+        unsafe { &*(&self.mmap[self.size()] as *const u8 as *const AtomicU8) }
     }
 
     fn buffer(&self) -> &[u8] {
-        &self.mmap[..self.size() - 1]
-    }
-
-    fn owner_mut(&mut self) -> *mut u8 {
-        let i = self.size();
-        &mut self.mmap[i]
+        &self.mmap[..self.size()]
     }
 
     fn buffer_mut(&mut self) -> &mut [u8] {
         let i = self.size();
-        &mut self.mmap[..i - 1]
+        &mut self.mmap[..i]
     }
 
     /// Returns the size of the data buffer
     fn size(&self) -> usize {
-        self.mmap.len() - 1
+        self.mmap.len() - OWNER_LEN
     }
 
+    /// Hands the buffer over, publishing the payload written before
     pub fn write_owner(&mut self, owner_id: u8) {
-        unsafe { self.owner_mut().write_volatile(owner_id) }
+        self.owner().store(owner_id, Ordering::Release)
     }
 
+    /// Returns the current owner
     pub fn current_owner(&self) -> u8 {
-        unsafe { self.owner().read_volatile() }
+        self.owner().load(Ordering::Acquire)
     }
 
     pub fn wait_for_owner(&mut self, owner_id: u8) -> &mut Self {
@@ -90,7 +88,7 @@ impl Read for TransferBuffer {
 pub fn nicer_naive_shared_memory_benchmark() {
     const TX: u8 = 0;
     const RX: u8 = 1;
-    let mut transfer_buffer = TransferBuffer::new(LMAX + 1, 0).expect("mmap failed");
+    let mut transfer_buffer = TransferBuffer::new(LMAX, 0).expect("mmap failed");
     let message_lengths = (0..).map(|i| (2 as usize).pow(i)).take_while(|i| i < &LMAX);
     match fork() {
         Ok(ForkResult::Parent { child, .. }) => {
@@ -190,11 +188,7 @@ pub fn naive_shared_memory_benchmark() {
                     10.0f64.powf(9.0) * (message_length * IMAX) as f64 / t2.as_nanos() as f64
                 );
             }
-            let mut sys = System::new();
-            sys.refresh_all();
-            if let Some(p) = sys.get_process(i32::from(child)) {
-                while p.status().to_string() != "Zombie" {}
-            }
+            wait_for_process(child);
             println!("Parent shutting down");
         }
         Ok(ForkResult::Child) => {
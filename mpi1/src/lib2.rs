@@ -1,6 +1,7 @@
 #![allow(dead_code)]
 use std::cell::UnsafeCell;
 use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
 
 use memmap::{MmapMut, MmapOptions};
 
@@ -9,6 +10,9 @@ const SENDER: u8 = 0;
 const RECEIVER: u8 = 1;
 struct Channel {}
 
+/// Length of the owner byte behind the payload
+const OWNER_LEN: usize = 1;
+
 #[derive(Debug)]
 struct TransferBuffer {
     mmap: MmapMut,
@@ -18,7 +22,7 @@ impl TransferBuffer {
     pub fn new(size: usize, owner: u8) -> Result<Self> {
         let mut mmap_options = MmapOptions::new();
         mmap_options
-            .len(size + 1)
+            .len(size + OWNER_LEN)
             .map_anon()
             .map(|mmap| TransferBuffer { mmap })
             .map(|mut buf| {
@@ -27,35 +31,33 @@ impl TransferBuffer {
             })
     }
 
-    fn owner(&self) -> *const u8 {
-        &self.mmap[self.size()]
+    /// The owner byte, an `AtomicU8` has the same layout
+    fn owner(&self) -> &AtomicU8 {
+        unsafe { &*(&self.mmap[self.size()] as *const u8 as *const AtomicU8) }
     }
 
     fn buffer(&self) -> &[u8] {
-        &self.mmap[..self.size() - 1]
-    }
-
-    fn owner_mut(&mut self) -> *mut u8 {
-        let i = self.size();
-        &mut self.mmap[i]
+        &self.mmap[..self.size()]
     }
 
     fn buffer_mut(&mut self) -> &mut [u8] {
         let i = self.size();
-        &mut self.mmap[..i - 1]
+        &mut self.mmap[..i]
     }
 
     /// Returns the size of the data buffer
     fn size(&self) -> usize {
-        self.mmap.len() - 1
+        self.mmap.len() - OWNER_LEN
     }
 
+    /// Hands the buffer over, publishing the payload written before
     pub fn write_owner(&mut self, owner_id: u8) {
-        unsafe { self.owner_mut().write_volatile(owner_id) }
+        self.owner().store(owner_id, Ordering::Release)
     }
 
+    /// Returns the current owner
     pub fn current_owner(&self) -> u8 {
-        unsafe { self.owner().read_volatile() }
+        self.owner().load(Ordering::Acquire)
     }
 
     pub fn wait_for_owner(&self, owner_id: u8) -> &Self {
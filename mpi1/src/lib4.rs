@@ -3,7 +3,8 @@ use std::cell::UnsafeCell;
 use std::io;
 use std::io::{Error, ErrorKind, Read, Write};
 use std::marker::PhantomData;
-use std::mem::size_of;
+use std::mem::{size_of, MaybeUninit};
+use std::sync::atomic::{AtomicU8, Ordering};
 
 use memmap::{MmapMut, MmapOptions};
 
@@ -12,6 +13,9 @@ const SENDER: u8 = 0;
 const RECEIVER: u8 = 1;
 struct Channel {}
 
+/// Length of the owner byte behind the payload
+const OWNER_LEN: usize = 1;
+
 #[derive(Debug)]
 struct TransferBuffer {
     mmap: MmapMut,
@@ -21,7 +25,7 @@ impl TransferBuffer {
     pub fn new(size: usize, owner: u8) -> io::Result<Self> {
         let mut mmap_options = MmapOptions::new();
         mmap_options
-            .len(size + 2)
+            .len(size + OWNER_LEN)
             .map_anon()
             .map(|mmap| TransferBuffer { mmap })
             .map(|mut buf| {
@@ -30,35 +34,33 @@ impl TransferBuffer {
             })
     }
 
-    fn owner(&self) -> *const u8 {
-        &self.mmap[self.size()]
+    /// The owner byte, an `AtomicU8` has the same layout
+    fn owner(&self) -> &AtomicU8 {
+        unsafe { &*(&self.mmap[self.size()] as *const u8 as *const AtomicU8) }
     }
 
     fn buffer(&self) -> &[u8] {
-        &self.mmap[..self.size() - 1]
-    }
-
-    fn owner_mut(&mut self) -> *mut u8 {
-        let i = self.size();
-        &mut self.mmap[i]
+        &self.mmap[..self.size()]
     }
 
     fn buffer_mut(&mut self) -> &mut [u8] {
         let i = self.size();
-        &mut self.mmap[..i - 1]
+        &mut self.mmap[..i]
     }
 
     /// Returns the size of the data buffer
     fn size(&self) -> usize {
-        self.mmap.len() - 1
+        self.mmap.len() - OWNER_LEN
     }
 
+    /// Hands the buffer over, publishing the payload written before
     pub fn write_owner(&mut self, owner_id: u8) {
-        unsafe { self.owner_mut().write_volatile(owner_id) }
+        self.owner().store(owner_id, Ordering::Release)
     }
 
+    /// Returns the current owner
     pub fn current_owner(&self) -> u8 {
-        unsafe { self.owner().read_volatile() }
+        self.owner().load(Ordering::Acquire)
     }
 
     pub fn wait_for_owner(&self, owner_id: u8) -> &Self {
@@ -161,12 +163,22 @@ impl<T: Copy> Receiver<T> {
         }
     }
 
+    /// Receives the next value. Fails with `ErrorKind::UnexpectedEof` if the buffer didn't
+    /// hold a whole `T`.
     pub fn get(&mut self) -> io::Result<T> {
-        // let mut buf: [u8; size_of::<T>()] = [0; size_of::<T>()];
-        let mut buf: Vec<u8> = vec![0; size_of::<T>()];
-        self.read(&mut buf)?;
-        let (_head, body, _tail) = unsafe { buf.align_to::<T>() };
-        Ok(body[0])
+        // read straight into a `T`, a byte buffer isn't necessarily aligned for one
+        let mut value = MaybeUninit::<T>::zeroed();
+        let buf = unsafe {
+            std::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>())
+        };
+        let r = self.read(buf)?;
+        if r != size_of::<T>() {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("got {} of {} bytes", r, size_of::<T>()),
+            ));
+        }
+        Ok(unsafe { value.assume_init() })
     }
 }
 
@@ -195,6 +207,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn full_payload_keeps_owner() {
+        let mut buffer = TransferBuffer::new(16, RECEIVER).unwrap();
+        assert_eq!(buffer.buffer_mut().len(), 16);
+        for byte in buffer.buffer_mut() {
+            *byte = 0xff;
+        }
+        assert_eq!(buffer.current_owner(), RECEIVER);
+        assert_eq!(buffer.mmap.len(), 16 + OWNER_LEN);
+    }
+
+    #[test]
+    fn get_aligned_type() {
+        #[derive(Debug, Copy, Clone, PartialEq)]
+        #[repr(align(32))]
+        struct Aligned {
+            x: f64,
+            y: u8,
+        }
+
+        let mut receiver = Receiver::<Aligned>::new().unwrap();
+        for i in 0..16 {
+            let data = Aligned {
+                x: i as f64 * 0.5,
+                y: i,
+            };
+            receiver.new_sender().send(&data).unwrap();
+            assert_eq!(receiver.get().unwrap(), data);
+        }
+    }
+
     #[test]
     fn simple_transfer() {
         let mut receiver1 = Receiver::<usize>::new().unwrap();
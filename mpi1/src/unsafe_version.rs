@@ -3,8 +3,8 @@ use std::str;
 use std::time::Instant;
 
 use memmap::{MmapMut, MmapOptions};
+use nix::sys::wait::waitpid;
 use nix::unistd::{fork, ForkResult};
-use sysinfo::{ProcessExt, System, SystemExt};
 
 const IMAX: usize = 100000;
 const LMAX: usize = 1024 * 256;
@@ -47,11 +47,7 @@ pub fn naive_shared_memory_benchmark() {
                     10.0f64.powf(9.0) * (message_length * IMAX) as f64 / t2.as_nanos() as f64
                 );
             }
-            let mut sys = System::new();
-            sys.refresh_all();
-            if let Some(p) = sys.get_process(i32::from(child)) {
-                while p.status().to_string() != "Zombie" {}
-            }
+            let _ = waitpid(child, None);
             println!("Parent shutting down");
         }
         Ok(ForkResult::Child) => {
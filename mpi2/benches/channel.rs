@@ -0,0 +1,53 @@
+//! Latency and bandwidth of a channel between two processes, for message sizes from 1 byte
+//! up to 1 MiB.
+//!
+//! Criterion calls the measured routine itself, many times over, so the peer process can't be
+//! forked inside of it. Instead every message size gets a peer that is forked once up front
+//! and echoes each message back over a second channel, and the routine measures one round
+//! trip. The peer is killed once criterion is done with its size. Everything the peer needs is
+//! allocated before forking, since the benchmark process may have other threads running.
+
+use std::io::{Read, Write};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use mpi2::channel::Receiver;
+use nix::sys::signal::{kill, Signal};
+use nix::sys::wait::waitpid;
+use nix::unistd::{fork, ForkResult};
+
+const MAX_LEN: usize = 1 << 20;
+
+fn round_trip(c: &mut Criterion) {
+    let mut group = c.benchmark_group("round_trip");
+    for len in (0..=20).map(|shift| 1 << shift) {
+        let mut ping = Receiver::<u8>::with_buffer_size(MAX_LEN).unwrap();
+        let mut ping_tx = ping.new_sender();
+        let mut pong = Receiver::<u8>::with_buffer_size(MAX_LEN).unwrap();
+        let mut pong_tx = pong.new_sender();
+        let mut buf = vec![0; len];
+
+        match fork() {
+            Ok(ForkResult::Parent { child }) => {
+                // the message crosses the channel twice per iteration
+                group.throughput(Throughput::Bytes(2 * len as u64));
+                group.bench_with_input(BenchmarkId::from_parameter(len), &len, |b, _| {
+                    b.iter(|| {
+                        ping_tx.write_all(&buf).unwrap();
+                        pong.read_exact(&mut buf).unwrap();
+                    })
+                });
+                kill(child, Signal::SIGKILL).unwrap();
+                waitpid(child, None).unwrap();
+            }
+            Ok(ForkResult::Child) => loop {
+                ping.read_exact(&mut buf).unwrap();
+                pong_tx.write_all(&buf).unwrap();
+            },
+            Err(e) => panic!("fork failed: {}", e),
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, round_trip);
+criterion_main!(benches);
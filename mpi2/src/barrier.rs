@@ -0,0 +1,167 @@
+use std::fs::OpenOptions;
+use std::io;
+use std::mem::size_of;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use memmap::{MmapMut, MmapOptions};
+
+/// Shared state of a barrier, living inside an anonymous mmap or a mapped file.
+#[repr(C)]
+struct BarrierState {
+    /// Number of processes that arrived in the current round
+    count: AtomicUsize,
+    /// Incremented by the leader every time a round completes
+    generation: AtomicUsize,
+}
+
+/// A barrier that synchronizes multiple processes, similar to `std::sync::Barrier`.
+///
+/// The barrier has to be created before forking so that all processes share the
+/// same underlying memory. It can be reused for any number of rounds: every round
+/// has its own generation, so a fast process entering the next round can't release
+/// a slow process that is still waiting in the previous one.
+#[derive(Debug)]
+pub struct SharedBarrier {
+    mmap: MmapMut,
+    n: usize,
+}
+
+/// Returned by `SharedBarrier::wait`, like `std::sync::BarrierWaitResult`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+    /// Exactly one process per round is the leader - the one that arrived last.
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl SharedBarrier {
+    /// Creates a barrier that blocks `n` processes. Like `std::sync::Barrier`, `n == 0`
+    /// behaves the same as `n == 1`.
+    pub fn new(n: usize) -> io::Result<Self> {
+        let mut mmap = MmapOptions::new()
+            .len(size_of::<BarrierState>())
+            .map_anon()?;
+        let state = mmap.as_mut_ptr() as *mut BarrierState;
+        unsafe {
+            state.write(BarrierState {
+                count: AtomicUsize::new(0),
+                generation: AtomicUsize::new(0),
+            })
+        };
+        Ok(SharedBarrier { mmap, n })
+    }
+
+    /// Creates a barrier for `n` processes backed by the file at `path`, for processes that
+    /// were already forked. They attach to it with `open_named` once the file exists. An
+    /// existing file is replaced.
+    pub fn create_named(path: &Path, n: usize) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        // zero filled, which is the initial state
+        file.set_len(size_of::<BarrierState>() as u64)?;
+        let mmap = unsafe {
+            MmapOptions::new()
+                .len(size_of::<BarrierState>())
+                .map_mut(&file)?
+        };
+        Ok(SharedBarrier { mmap, n })
+    }
+
+    /// Attaches to a barrier created by `create_named`
+    pub fn open_named(path: &Path, n: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mmap = unsafe {
+            MmapOptions::new()
+                .len(size_of::<BarrierState>())
+                .map_mut(&file)?
+        };
+        Ok(SharedBarrier { mmap, n })
+    }
+
+    fn state(&self) -> &BarrierState {
+        // the mmap is page aligned and was initialized in `new`
+        unsafe { &*(self.mmap.as_ptr() as *const BarrierState) }
+    }
+
+    /// Blocks until all `n` processes have called `wait` in the current round.
+    pub fn wait(&self) -> BarrierWaitResult {
+        let state = self.state();
+        let generation = state.generation.load(Ordering::Acquire);
+        if state.count.fetch_add(1, Ordering::AcqRel) + 1 >= self.n {
+            state.count.store(0, Ordering::Relaxed);
+            state.generation.fetch_add(1, Ordering::Release);
+            BarrierWaitResult(true)
+        } else {
+            while state.generation.load(Ordering::Acquire) == generation {
+                std::thread::yield_now();
+            }
+            BarrierWaitResult(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nix::sys::wait::waitpid;
+    use nix::unistd::{fork, ForkResult};
+
+    #[test]
+    fn one_leader_per_round() {
+        const N: usize = 4;
+        const ROUNDS: usize = 50;
+
+        let barrier = SharedBarrier::new(N).unwrap();
+        // one leader counter per round, shared between all processes
+        let mut leader_mmap = MmapOptions::new()
+            .len(ROUNDS * size_of::<AtomicUsize>())
+            .map_anon()
+            .unwrap();
+        let leaders = unsafe {
+            std::slice::from_raw_parts(leader_mmap.as_mut_ptr() as *const AtomicUsize, ROUNDS)
+        };
+
+        let mut children = Vec::new();
+        for _ in 1..N {
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => children.push(child),
+                Ok(ForkResult::Child) => {
+                    for leader_count in leaders {
+                        if barrier.wait().is_leader() {
+                            leader_count.fetch_add(1, Ordering::SeqCst);
+                        }
+                    }
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
+        for leader_count in leaders {
+            if barrier.wait().is_leader() {
+                leader_count.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        for child in children {
+            waitpid(child, None).unwrap();
+        }
+        for leader_count in leaders {
+            assert_eq!(leader_count.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[test]
+    fn single_process_is_always_leader() {
+        let barrier = SharedBarrier::new(1).unwrap();
+        assert!(barrier.wait().is_leader());
+        assert!(barrier.wait().is_leader());
+    }
+}
@@ -0,0 +1,143 @@
+use super::*;
+
+/// A send started with `Sender::isend`, like an `MPI_Request` from `MPI_Isend`.
+///
+/// The request borrows the sender, so no other operation can be started on the channel before
+/// it is done. Dropping an unfinished request cancels the send: the payload never reaches the
+/// receiver.
+#[must_use = "the message is only sent once the request completes"]
+#[derive(Debug)]
+pub struct SendRequest<'s, 'a, T> {
+    sender: &'s mut Sender<'a, T>,
+    /// The payload as long as it hasn't been written to the buffer
+    data: Option<T>,
+}
+
+/// A receive started with `Receiver::irecv`, like an `MPI_Request` from `MPI_Irecv`.
+///
+/// The request borrows the receiver, so no other operation can be started on the channel
+/// before it is done. Dropping an unfinished request leaves the message in the channel.
+#[must_use = "the message is only received once the request completes"]
+#[derive(Debug)]
+pub struct RecvRequest<'r, T> {
+    receiver: &'r mut Receiver<T>,
+    /// The message once it has been taken out of the buffer
+    value: Option<T>,
+}
+
+impl<'a, T: SharedSafe> Sender<'a, T> {
+    /// Starts sending `data` without blocking. The payload is written right away if the
+    /// receiver already picked up the previous message, otherwise once the request is
+    /// tested or waited on.
+    pub fn isend(&mut self, data: T) -> Result<SendRequest<'_, 'a, T>, ChannelError> {
+        check_size::<T>(self.get_buffer_ref()?)?;
+        let mut request = SendRequest {
+            sender: self,
+            data: Some(data),
+        };
+        request.test()?;
+        Ok(request)
+    }
+}
+
+impl<T: SharedSafe> SendRequest<'_, '_, T> {
+    /// Writes the payload if the buffer is free, without blocking. Returns whether the
+    /// message has been handed over to the receiver.
+    pub fn test(&mut self) -> Result<bool, ChannelError> {
+        if let Some(data) = self.data {
+            if self.sender.get_buffer_ref()?.current_owner() != SENDER {
+                return Ok(false);
+            }
+            self.sender.send(data)?;
+            self.data = None;
+        }
+        Ok(true)
+    }
+
+    /// Blocks until the message has been handed over to the receiver
+    pub fn wait(mut self) -> Result<(), ChannelError> {
+        if let Some(data) = self.data.take() {
+            self.sender.send(data)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: SharedSafe> Receiver<T> {
+    /// Starts receiving the next message without blocking
+    pub fn irecv(&mut self) -> RecvRequest<'_, T> {
+        RecvRequest {
+            receiver: self,
+            value: None,
+        }
+    }
+}
+
+impl<T: SharedSafe> RecvRequest<'_, T> {
+    /// Takes the message out of the channel if it arrived, without blocking. Returns whether
+    /// the request is complete, so that `wait` returns right away.
+    pub fn test(&mut self) -> Result<bool, ChannelError> {
+        if self.value.is_none() {
+            self.value = self.receiver.try_recv()?;
+        }
+        Ok(self.value.is_some())
+    }
+
+    /// Blocks until the message arrived and returns it
+    pub fn wait(self) -> Result<T, ChannelError> {
+        match self.value {
+            Some(value) => Ok(value),
+            None => self.receiver.recv(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nix::sys::wait::waitpid;
+
+    #[test]
+    fn irecv_overlaps_with_work() {
+        let mut receiver = Receiver::<u64>::new().unwrap();
+        let mut sender = receiver.new_sender();
+
+        match fork() {
+            Ok(ForkResult::Parent { child, .. }) => {
+                let mut request = receiver.irecv();
+                assert!(!request.test().unwrap());
+                // unrelated work while the child takes its time
+                let sum: u64 = (0..1_000_000).sum();
+                assert_eq!(request.wait().unwrap(), 42);
+                assert_eq!(sum, 499_999_500_000);
+                waitpid(child, None).unwrap();
+            }
+            Ok(ForkResult::Child) => {
+                std::thread::sleep(Duration::from_millis(100));
+                sender.send(42).unwrap();
+                std::process::exit(0);
+            }
+            Err(e) => panic!("fork failed: {}", e),
+        }
+    }
+
+    #[test]
+    fn isend_completes_once_buffer_is_free() {
+        let mut receiver = Receiver::<u32>::new().unwrap();
+        let mut sender = receiver.new_sender();
+
+        // the buffer is free, so the payload goes out right away
+        let mut first = sender.isend(1).unwrap();
+        assert!(first.test().unwrap());
+        first.wait().unwrap();
+        // the first message hasn't been received, so this one has to wait
+        let mut second = sender.isend(2).unwrap();
+        assert!(!second.test().unwrap());
+        drop(second);
+
+        assert_eq!(receiver.try_recv().unwrap(), Some(1));
+        // the dropped request never wrote its payload
+        assert_eq!(receiver.try_recv().unwrap(), None);
+    }
+}
@@ -0,0 +1,136 @@
+use super::*;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Bytes in front of every message holding the length of its serialized form
+const LENGTH_PREFIX: usize = size_of::<u64>();
+
+fn bincode_to_io(e: bincode::Error) -> io::Error {
+    Error::new(ErrorKind::InvalidData, e)
+}
+
+/// Receiving end of a channel for types that aren't `SharedSafe`, like `String` or `Vec`.
+///
+/// Messages are serialized with bincode into the transfer buffer, so its capacity has to be
+/// chosen up front for the largest serialized message.
+#[derive(Debug)]
+pub struct SerReceiver<T> {
+    buffer: TransferBuffer,
+    phantom_data: PhantomData<T>,
+}
+
+#[derive(Debug)]
+pub struct SerSender<'a, T> {
+    buffer: SenderBuffer<'a>,
+    phantom_data: PhantomData<T>,
+}
+
+impl<T: DeserializeOwned> SerReceiver<T> {
+    /// Creates a channel for messages of up to `capacity` bytes in serialized form
+    pub fn new(capacity: usize) -> io::Result<Self> {
+        let buffer =
+            TransferBuffer::new(LENGTH_PREFIX + capacity, SENDER, ChannelOptions::default())?;
+        Ok(SerReceiver {
+            buffer,
+            phantom_data: PhantomData,
+        })
+    }
+
+    pub fn new_sender(&mut self) -> SerSender<'_, T> {
+        SerSender {
+            buffer: SenderBuffer::Borrowed(&mut self.buffer),
+            phantom_data: PhantomData,
+        }
+    }
+
+    /// Receives and deserializes the next message. The message is consumed even if it can't
+    /// be deserialized, which fails with `ErrorKind::InvalidData`.
+    pub fn recv(&mut self) -> io::Result<T> {
+        self.buffer.wait_for_owner(RECEIVER);
+        let payload = self.buffer.buffer();
+        let mut length = [0; LENGTH_PREFIX];
+        length.copy_from_slice(&payload[..LENGTH_PREFIX]);
+        let length = u64::from_ne_bytes(length) as usize;
+        let t = bincode::deserialize(&payload[LENGTH_PREFIX..LENGTH_PREFIX + length])
+            .map_err(bincode_to_io);
+        self.buffer.write_owner(SENDER);
+        t
+    }
+}
+
+impl<T: Serialize> SerSender<'_, T> {
+    /// Serializes `data` into the channel.
+    ///
+    /// Fails with `ErrorKind::InvalidInput` without sending anything if the serialized form
+    /// doesn't fit into the capacity of the channel.
+    pub fn send(&mut self, data: &T) -> io::Result<()> {
+        let buf = self.buffer.get_mut();
+        let capacity = buf.size() - LENGTH_PREFIX;
+        let length = bincode::serialized_size(data).map_err(bincode_to_io)? as usize;
+        if length > capacity {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "serialized message of {} bytes exceeds channel capacity of {} bytes",
+                    length, capacity
+                ),
+            ));
+        }
+
+        buf.wait_for_owner(SENDER);
+        let payload = buf.buffer_mut();
+        payload[..LENGTH_PREFIX].copy_from_slice(&(length as u64).to_ne_bytes());
+        let written = bincode::serialize_into(&mut payload[LENGTH_PREFIX..], data);
+        // hand the buffer over even on failure, the receiver then reports the broken message
+        buf.write_owner(RECEIVER);
+        written.map_err(bincode_to_io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nix::sys::wait::waitpid;
+
+    #[test]
+    fn strings_round_trip() {
+        let messages: Vec<Vec<String>> = vec![
+            vec![],
+            vec!["".to_string()],
+            vec!["short".to_string(), "a bit longer".to_string()],
+            (0..20).map(|i| "x".repeat(i * 10)).collect(),
+        ];
+        let mut receiver = SerReceiver::<Vec<String>>::new(4096).unwrap();
+        let mut sender = receiver.new_sender();
+
+        match fork() {
+            Ok(ForkResult::Parent { child, .. }) => {
+                for message in messages.iter() {
+                    assert_eq!(&receiver.recv().unwrap(), message);
+                }
+                waitpid(child, None).unwrap();
+            }
+            Ok(ForkResult::Child) => {
+                for message in messages.iter() {
+                    sender.send(message).unwrap();
+                }
+                std::process::exit(0);
+            }
+            Err(e) => panic!("fork failed: {}", e),
+        }
+    }
+
+    #[test]
+    fn oversized_message_is_rejected() {
+        let mut receiver = SerReceiver::<String>::new(16).unwrap();
+        let mut sender = receiver.new_sender();
+
+        let err = sender.send(&"y".repeat(100)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        // nothing was sent, so the channel still works
+        sender.send(&"fits".to_string()).unwrap();
+        assert_eq!(receiver.recv().unwrap(), "fits");
+    }
+}
@@ -0,0 +1,675 @@
+//! Collective operations over the process group spawned by `init`, modeled after their MPI
+//! counterparts. Every rank of the group has to take part in a collective call, otherwise
+//! the others block forever.
+
+use std::io::{self, Error, ErrorKind};
+
+use crate::channel::SharedSafe;
+use crate::transport::Transport;
+use crate::Communicator;
+
+/// Rank of `rank` in a group renumbered so that `root` becomes 0
+fn relative_rank<L>(comm: &Communicator<L>, root: usize, rank: usize) -> usize {
+    (rank + comm.n_processes - root) % comm.n_processes
+}
+
+/// Inverse of `relative_rank`
+fn absolute_rank<L>(comm: &Communicator<L>, root: usize, relative: usize) -> usize {
+    (relative + root) % comm.n_processes
+}
+
+/// Copies `value` of rank `root` into `value` on every other rank, like `MPI_Bcast`.
+///
+/// The value travels along a binomial tree rooted at `root`, so it reaches all ranks after
+/// O(log n) sequential steps. With a single process this does nothing.
+///
+/// # Panics
+///
+/// Panics if `root` isn't a rank of the group.
+pub fn broadcast<T: SharedSafe, L: Transport>(comm: &Communicator<L>, root: usize, value: &mut T) {
+    assert!(
+        root < comm.n_processes,
+        "root {} out of range for {} processes",
+        root,
+        comm.n_processes
+    );
+    let n = comm.n_processes;
+    let relative = relative_rank(comm, root, comm.rank);
+
+    // receive from the parent: the rank that differs in the lowest set bit
+    let mut mask = 1;
+    while mask < n {
+        if relative & mask != 0 {
+            *value = comm.recv_from(absolute_rank(comm, root, relative - mask));
+            break;
+        }
+        mask <<= 1;
+    }
+    // pass it on to the children below that bit, largest subtree first
+    mask >>= 1;
+    while mask > 0 {
+        if relative + mask < n {
+            comm.send_to(absolute_rank(comm, root, relative + mask), *value);
+        }
+        mask >>= 1;
+    }
+}
+
+/// Combines the `value`s of all ranks with `op` and returns the result on rank `root`, like
+/// `MPI_Reduce`. All other ranks get `None`.
+///
+/// Values are combined pairwise along the same binomial tree `broadcast` uses, in reverse.
+/// The order in which `op` sees the values is unspecified, so it should be associative and
+/// commutative - otherwise the result depends on the number of processes and on `root`.
+///
+/// # Panics
+///
+/// Panics if `root` isn't a rank of the group.
+pub fn reduce<T: SharedSafe, L: Transport>(
+    comm: &Communicator<L>,
+    root: usize,
+    value: T,
+    op: impl Fn(T, T) -> T,
+) -> Option<T> {
+    assert!(
+        root < comm.n_processes,
+        "root {} out of range for {} processes",
+        root,
+        comm.n_processes
+    );
+    let n = comm.n_processes;
+    let relative = relative_rank(comm, root, comm.rank);
+
+    let mut acc = value;
+    let mut mask = 1;
+    while mask < n {
+        if relative & mask != 0 {
+            // all children have been collected, hand the partial result to the parent
+            comm.send_to(absolute_rank(comm, root, relative - mask), acc);
+            return None;
+        }
+        if relative + mask < n {
+            let partial = comm.recv_from(absolute_rank(comm, root, relative + mask));
+            acc = op(acc, partial);
+        }
+        mask <<= 1;
+    }
+    Some(acc)
+}
+
+/// Combines the `value`s of all ranks with `op` and returns the result on every rank, like
+/// `MPI_Allreduce`.
+///
+/// The result is reduced on rank 0 and then broadcast, so all ranks get an identical value,
+/// bit for bit. What that value is depends on the order in which `op` combines the values,
+/// unless `op` is associative and commutative like for `reduce`. Floating point addition,
+/// for example, may give a slightly different sum for another number of processes.
+pub fn allreduce<T: SharedSafe, L: Transport>(
+    comm: &Communicator<L>,
+    value: T,
+    op: impl Fn(T, T) -> T,
+) -> T {
+    let mut result = reduce(comm, 0, value, op).unwrap_or(value);
+    broadcast(comm, 0, &mut result);
+    result
+}
+
+/// Hands `send[i]` of rank `root` to rank `i`, like `MPI_Scatter`. Only the root's `send`
+/// is used, the other ranks can pass `None`. The root keeps its own element without sending
+/// it anywhere.
+///
+/// If the root's `send` is missing or doesn't hold exactly one element per rank, the root
+/// fails with `ErrorKind::InvalidInput`. It broadcasts that before sending anything, so all
+/// other ranks fail as well instead of waiting for their element.
+///
+/// # Panics
+///
+/// Panics if `root` isn't a rank of the group.
+pub fn scatter<T: SharedSafe, L: Transport>(
+    comm: &Communicator<L>,
+    root: usize,
+    send: Option<&[T]>,
+) -> io::Result<T> {
+    assert!(
+        root < comm.n_processes,
+        "root {} out of range for {} processes",
+        root,
+        comm.n_processes
+    );
+    let checked = if comm.rank == root {
+        match send {
+            None => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "the root of scatter needs something to send",
+            )),
+            Some(send) if send.len() != comm.n_processes => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "scatter needs one element per rank, got {} for {} processes",
+                    send.len(),
+                    comm.n_processes
+                ),
+            )),
+            Some(send) => Ok(send),
+        }
+    } else {
+        Ok(&[][..])
+    };
+    let mut ok = checked.is_ok();
+    broadcast(comm, root, &mut ok);
+    if comm.rank != root {
+        if !ok {
+            return Err(failed_on_root("scatter", root));
+        }
+        return Ok(comm.recv_from(root));
+    }
+    let send = checked?;
+    for (rank, &value) in send.iter().enumerate() {
+        if rank != root {
+            comm.send_to(rank, value);
+        }
+    }
+    Ok(send[root])
+}
+
+/// What the other ranks get when the arguments of a collective call were rejected on `root`
+fn failed_on_root(name: &str, root: usize) -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        format!("{} failed on root {}", name, root),
+    )
+}
+
+/// Collects the `value` of every rank on rank `root`, like `MPI_Gather`. The root gets them
+/// indexed by rank, all other ranks get `None`.
+///
+/// # Panics
+///
+/// Panics if `root` isn't a rank of the group.
+pub fn gather<T: SharedSafe, L: Transport>(
+    comm: &Communicator<L>,
+    root: usize,
+    value: T,
+) -> Option<Vec<T>> {
+    assert!(
+        root < comm.n_processes,
+        "root {} out of range for {} processes",
+        root,
+        comm.n_processes
+    );
+    if comm.rank != root {
+        comm.send_to(root, value);
+        return None;
+    }
+    let gathered = (0..comm.n_processes)
+        .map(|rank| {
+            if rank == root {
+                value
+            } else {
+                comm.recv_from(rank)
+            }
+        })
+        .collect();
+    Some(gathered)
+}
+
+/// Like `scatter`, but rank `i` gets `counts[i]` elements, like `MPI_Scatterv`. The root's
+/// `send` holds the parts for all ranks back to back in rank order. Every rank has to pass
+/// the same `counts`, a rank with a count of 0 gets an empty `Vec`.
+///
+/// If the root's `send` is missing or doesn't hold as many elements as all counts together,
+/// every rank fails with `ErrorKind::InvalidInput`, like with `scatter`.
+///
+/// # Panics
+///
+/// Panics if `root` isn't a rank of the group, or if `counts` doesn't hold one count per
+/// rank.
+pub fn scatterv<T: SharedSafe, L: Transport>(
+    comm: &Communicator<L>,
+    root: usize,
+    send: Option<&[T]>,
+    counts: &[usize],
+) -> io::Result<Vec<T>> {
+    assert!(
+        root < comm.n_processes,
+        "root {} out of range for {} processes",
+        root,
+        comm.n_processes
+    );
+    assert_eq!(
+        counts.len(),
+        comm.n_processes,
+        "scatterv needs one count per rank, got {} for {} processes",
+        counts.len(),
+        comm.n_processes
+    );
+    let checked = if comm.rank == root {
+        let total = counts.iter().sum::<usize>();
+        match send {
+            None => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "the root of scatterv needs something to send",
+            )),
+            Some(send) if send.len() != total => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "scatterv got {} elements for counts adding up to {}",
+                    send.len(),
+                    total
+                ),
+            )),
+            Some(send) => Ok(send),
+        }
+    } else {
+        Ok(&[][..])
+    };
+    let mut ok = checked.is_ok();
+    broadcast(comm, root, &mut ok);
+    if comm.rank != root {
+        if !ok {
+            return Err(failed_on_root("scatterv", root));
+        }
+        return Ok(comm.recv_vec_from(root, counts[comm.rank]));
+    }
+    let send = checked?;
+    let mut own = Vec::new();
+    let mut rest = send;
+    for (rank, &count) in counts.iter().enumerate() {
+        let (part, tail) = rest.split_at(count);
+        if rank == root {
+            own = part.to_vec();
+        } else {
+            comm.send_slice_to(rank, part);
+        }
+        rest = tail;
+    }
+    Ok(own)
+}
+
+/// Like `gather`, but rank `i` contributes `counts[i]` elements, like `MPI_Gatherv`. The
+/// root gets the contributions of all ranks back to back in rank order, all other ranks get
+/// `None`. Every rank has to pass the same `counts`.
+///
+/// If `send` doesn't hold as many elements as the count of the calling rank on any rank,
+/// every rank fails with `ErrorKind::InvalidInput`. The ranks agree on that with an
+/// `allreduce` before anything is sent.
+///
+/// # Panics
+///
+/// Panics if `root` isn't a rank of the group, or if `counts` doesn't hold one count per
+/// rank.
+pub fn gatherv<T: SharedSafe, L: Transport>(
+    comm: &Communicator<L>,
+    root: usize,
+    send: &[T],
+    counts: &[usize],
+) -> io::Result<Option<Vec<T>>> {
+    assert!(
+        root < comm.n_processes,
+        "root {} out of range for {} processes",
+        root,
+        comm.n_processes
+    );
+    assert_eq!(
+        counts.len(),
+        comm.n_processes,
+        "gatherv needs one count per rank, got {} for {} processes",
+        counts.len(),
+        comm.n_processes
+    );
+    let own_ok = send.len() == counts[comm.rank];
+    if !allreduce(comm, own_ok, |a, b| a && b) {
+        if own_ok {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "gatherv failed on another rank",
+            ));
+        }
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "gatherv got {} elements on rank {} with a count of {}",
+                send.len(),
+                comm.rank,
+                counts[comm.rank]
+            ),
+        ));
+    }
+    if comm.rank != root {
+        comm.send_slice_to(root, send);
+        return Ok(None);
+    }
+    let mut gathered = Vec::with_capacity(counts.iter().sum());
+    for (rank, &count) in counts.iter().enumerate() {
+        if rank == root {
+            gathered.extend_from_slice(send);
+        } else {
+            gathered.extend(comm.recv_vec_from::<T>(rank, count));
+        }
+    }
+    Ok(Some(gathered))
+}
+
+/// Sends `send[j]` to rank `j` for every rank of the group and returns what the others sent
+/// to this one, with the element from rank `i` at index `i`, like `MPI_Alltoall`.
+///
+/// The exchange takes `n` steps. In step `k` every rank pairs up with rank `k - rank` modulo
+/// `n`, which in turn pairs up with it, and the lower rank of a pair sends first. So no two
+/// ranks can wait on each other, however large a `T` is. The own element is copied locally.
+///
+/// # Panics
+///
+/// Panics if `send` doesn't hold exactly one element per rank.
+pub fn alltoall<T: SharedSafe, L: Transport>(comm: &Communicator<L>, send: &[T]) -> Vec<T> {
+    let n = comm.n_processes;
+    assert_eq!(
+        send.len(),
+        n,
+        "alltoall needs one element per rank, got {} for {} processes",
+        send.len(),
+        n
+    );
+    let mut received = send.to_vec();
+    for step in 0..n {
+        let partner = (step + n - comm.rank) % n;
+        if partner < comm.rank {
+            received[partner] = comm.recv_from(partner);
+            comm.send_to(partner, send[partner]);
+        } else if partner > comm.rank {
+            comm.send_to(partner, send[partner]);
+            received[partner] = comm.recv_from(partner);
+        }
+    }
+    received
+}
+
+/// Blocks until every rank of the group has called `barrier`, like `MPI_Barrier`.
+///
+/// Can be called any number of times in a row: a rank that already left one barrier and
+/// enters the next can't release ranks still waiting in the previous one.
+pub fn barrier<L>(comm: &Communicator<L>) {
+    comm.barrier.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::mem::size_of;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use memmap::MmapOptions;
+
+    use crate::spawn_processes;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Default)]
+    struct Payload {
+        a: u64,
+        b: f64,
+        c: [i32; 3],
+    }
+    unsafe impl SharedSafe for Payload {}
+
+    #[test]
+    fn broadcast_from_nonzero_root() {
+        let data = Payload {
+            a: 42,
+            b: -1.5,
+            c: [1, 2, 3],
+        };
+        let comm = spawn_processes(4, false).unwrap();
+        let mut value = if comm.rank == 2 {
+            data
+        } else {
+            Payload::default()
+        };
+        broadcast(&comm, 2, &mut value);
+
+        // collect what every rank got on rank 0, a failing assert in another rank would go
+        // unnoticed
+        if comm.rank == 0 {
+            assert_eq!(value, data);
+            for rank in 1..comm.n_processes {
+                assert_eq!(comm.recv_from::<Payload>(rank), data);
+            }
+        } else {
+            comm.send_to(0, value);
+            std::process::exit(0);
+        }
+    }
+
+    #[test]
+    fn broadcast_single_process() {
+        let comm = spawn_processes(1, false).unwrap();
+        let mut value = 7u32;
+        broadcast(&comm, 0, &mut value);
+        assert_eq!(value, 7);
+    }
+
+    #[test]
+    fn reduce_sum() {
+        let comm = spawn_processes(5, false).unwrap();
+        let at_zero = reduce(&comm, 0, comm.rank, |a, b| a + b);
+        let at_three = reduce(&comm, 3, comm.rank as u64 + 1, |a, b| a * b);
+
+        match comm.rank {
+            0 => {
+                assert_eq!(at_zero, Some((0..5).sum()));
+                assert_eq!(at_three, None);
+                assert_eq!(comm.recv_from::<u64>(3), (1..=5).product());
+                for rank in 1..5 {
+                    assert!(comm.recv_from::<bool>(rank), "rank {} got a result", rank);
+                }
+            }
+            rank => {
+                if rank == 3 {
+                    comm.send_to(0, at_three.unwrap());
+                }
+                let only_on_root = at_zero.is_none() && (rank == 3 || at_three.is_none());
+                comm.send_to(0, only_on_root);
+                std::process::exit(0);
+            }
+        }
+    }
+
+    #[test]
+    fn allreduce_reaches_every_rank() {
+        let comm = spawn_processes(4, false).unwrap();
+        let sum = allreduce(&comm, comm.rank, |a, b| a + b);
+        let max = allreduce(&comm, (comm.rank as f64 - 1.5).abs(), f64::max);
+
+        if comm.rank == 0 {
+            assert_eq!((sum, max), (6, 1.5));
+            for rank in 1..4 {
+                assert_eq!(comm.recv_from::<(usize, f64)>(rank), (6, 1.5));
+            }
+        } else {
+            comm.send_to(0, (sum, max));
+            std::process::exit(0);
+        }
+    }
+
+    #[test]
+    fn scatter_then_gather() {
+        let comm = spawn_processes(4, false).unwrap();
+        let send = [10, 20, 30, 40];
+        let root_send = if comm.rank == 2 {
+            Some(&send[..])
+        } else {
+            None
+        };
+        let value = scatter(&comm, 2, root_send).unwrap() + comm.rank;
+        let gathered = gather(&comm, 0, value);
+
+        if comm.rank == 0 {
+            assert_eq!(gathered, Some(vec![10, 21, 32, 43]));
+            for rank in 1..4 {
+                assert!(comm.recv_from::<bool>(rank), "rank {} got a result", rank);
+            }
+        } else {
+            comm.send_to(0, gathered.is_none());
+            std::process::exit(0);
+        }
+    }
+
+    #[test]
+    fn scatter_fails_on_every_rank() {
+        let comm = spawn_processes(3, false).unwrap();
+        let send = [1u32, 2];
+        let missing = scatter::<u32, _>(&comm, 0, None).map_err(|e| e.kind());
+        let short = scatter(&comm, 1, Some(&send[..])).map_err(|e| e.kind());
+        // the group is still usable afterwards
+        let value = scatter(&comm, 1, Some(&[5, 6, 7][..])).unwrap();
+
+        let expected = (Err(ErrorKind::InvalidInput), Err(ErrorKind::InvalidInput));
+        if comm.rank == 0 {
+            assert_eq!((missing, short), expected);
+            assert_eq!(value, 5);
+            for rank in 1..3 {
+                assert!(comm.recv_from::<bool>(rank), "rank {} didn't fail", rank);
+            }
+        } else {
+            comm.send_to(
+                0,
+                (missing, short) == expected && value == 5 + comm.rank as u32,
+            );
+            std::process::exit(0);
+        }
+    }
+
+    #[test]
+    fn scatterv_then_gatherv() {
+        let comm = spawn_processes(3, false).unwrap();
+        let send = [1u32, 2, 3, 4, 5, 6];
+        let root_send = if comm.rank == 1 {
+            Some(&send[..])
+        } else {
+            None
+        };
+        let part = scatterv(&comm, 1, root_send, &[1, 3, 2]).unwrap();
+        let scaled: Vec<_> = part.iter().map(|x| x * 10).collect();
+        let gathered = gatherv(&comm, 0, &scaled, &[1, 3, 2]).unwrap();
+        // a rank without elements neither blocks nor gets any
+        let sparse = scatterv(&comm, 0, Some(&send[..2]), &[0, 0, 2]).unwrap();
+        let regathered = gatherv(&comm, 0, &sparse, &[0, 0, 2]).unwrap();
+
+        if comm.rank == 0 {
+            assert_eq!(part, [1]);
+            assert_eq!(gathered, Some(vec![10, 20, 30, 40, 50, 60]));
+            assert_eq!(regathered, Some(vec![1, 2]));
+            for rank in 1..3 {
+                assert_eq!(comm.recv_from::<usize>(rank), [3, 2][rank - 1]);
+                assert!(comm.recv_from::<bool>(rank), "rank {} got a result", rank);
+            }
+        } else {
+            comm.send_to(0, part.len());
+            comm.send_to(0, gathered.is_none() && regathered.is_none());
+            std::process::exit(0);
+        }
+    }
+
+    #[test]
+    fn scatterv_gatherv_fail_on_every_rank() {
+        let comm = spawn_processes(3, false).unwrap();
+        let send = [1u32, 2, 3, 4, 5, 6];
+        // the counts add up to one element more than the root has
+        let scattered = scatterv(&comm, 2, Some(&send[..]), &[2, 2, 3]).map_err(|e| e.kind());
+        // only rank 1 contributes the wrong number of elements
+        let own = vec![0u32; if comm.rank == 1 { 3 } else { 2 }];
+        let gathered = gatherv(&comm, 0, &own, &[2, 2, 2]).map_err(|e| e.kind());
+
+        let ok =
+            scattered == Err(ErrorKind::InvalidInput) && gathered == Err(ErrorKind::InvalidInput);
+        if comm.rank == 0 {
+            assert!(ok, "{:?} {:?}", scattered, gathered);
+            for rank in 1..3 {
+                assert!(comm.recv_from::<bool>(rank), "rank {} didn't fail", rank);
+            }
+        } else {
+            comm.send_to(0, ok);
+            std::process::exit(0);
+        }
+    }
+
+    #[test]
+    fn alltoall_transposes() {
+        const N: usize = 4;
+        let comm = spawn_processes(N, false).unwrap();
+        let send: Vec<_> = (0..N).map(|dst| (comm.rank, dst)).collect();
+        let mut received = [(0, 0); N];
+        received.copy_from_slice(&alltoall(&comm, &send));
+
+        if comm.rank == 0 {
+            assert_eq!(received, [(0, 0), (1, 0), (2, 0), (3, 0)]);
+            for rank in 1..N {
+                let expected: Vec<_> = (0..N).map(|src| (src, rank)).collect();
+                assert_eq!(
+                    comm.recv_from::<[(usize, usize); N]>(rank).to_vec(),
+                    expected
+                );
+            }
+        } else {
+            comm.send_to(0, received);
+            std::process::exit(0);
+        }
+    }
+
+    #[test]
+    fn alltoall_large_elements() {
+        // several pages per element, so every send blocks until the partner receives
+        const N: usize = 3;
+        const LEN: usize = 3000;
+        let comm = spawn_processes(N, false).unwrap();
+        let send: Vec<_> = (0..N)
+            .map(|dst| [(comm.rank * N + dst) as u64; LEN])
+            .collect();
+        let received = alltoall(&comm, &send);
+
+        let ok = received
+            .iter()
+            .enumerate()
+            .all(|(src, chunk)| chunk.iter().all(|&x| x == (src * N + comm.rank) as u64));
+        if comm.rank == 0 {
+            assert!(ok);
+            for rank in 1..N {
+                assert!(comm.recv_from::<bool>(rank), "rank {} got wrong data", rank);
+            }
+        } else {
+            comm.send_to(0, ok);
+            std::process::exit(0);
+        }
+    }
+
+    #[test]
+    fn barrier_waits_for_all_ranks() {
+        const N: usize = 8;
+        const ROUNDS: usize = 3;
+
+        let mut mmap = MmapOptions::new()
+            .len(size_of::<AtomicUsize>())
+            .map_anon()
+            .unwrap();
+        let counter = unsafe { &*(mmap.as_mut_ptr() as *const AtomicUsize) };
+
+        let comm = spawn_processes(N, false).unwrap();
+        let mut seen = [0; ROUNDS];
+        for seen in seen.iter_mut() {
+            counter.fetch_add(1, Ordering::SeqCst);
+            barrier(&comm);
+            *seen = counter.load(Ordering::SeqCst);
+            // nobody may increment for the next round before everyone has read this one
+            barrier(&comm);
+        }
+
+        if comm.rank == 0 {
+            let expected: Vec<_> = (1..=ROUNDS).map(|round| round * N).collect();
+            assert_eq!(seen.to_vec(), expected);
+            for rank in 1..N {
+                assert_eq!(comm.recv_from::<[usize; ROUNDS]>(rank).to_vec(), expected);
+            }
+        } else {
+            comm.send_to(0, seen);
+            std::process::exit(0);
+        }
+    }
+}
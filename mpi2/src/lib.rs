@@ -1,81 +1,822 @@
 #![allow(dead_code)]
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::env;
+use std::mem::MaybeUninit;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use derive_new::*;
-use nix::unistd::{fork, ForkResult, Pid};
+use memmap::{MmapMut, MmapOptions};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{fork, getpid, ForkResult, Pid};
 use sysinfo::{Process, ProcessExt, Signal, System, SystemExt};
 
-mod channel {
+pub mod barrier;
+pub mod collective;
+pub mod ring;
+pub mod transport;
+
+use transport::Transport;
+
+pub mod channel {
     use super::*;
 
     use std::cell::UnsafeCell;
+    #[cfg(target_os = "linux")]
+    use std::ffi::CString;
+    use std::fmt;
+    use std::fs::{File, OpenOptions};
+    use std::hint::spin_loop;
     use std::io;
     use std::io::{Error, ErrorKind, Read, Write};
     use std::marker::PhantomData;
-    use std::mem::size_of;
+    use std::mem::{align_of, size_of};
+    use std::ops::{Deref, Range};
+    use std::os::unix::io::{FromRawFd, RawFd};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
     use std::time::{Duration, Instant};
 
     use memmap::{MmapMut, MmapOptions};
-    use nix::unistd::{fork, ForkResult};
+    use nix::errno::Errno;
+    use nix::poll::{poll, PollFd, PollFlags};
+    #[cfg(target_os = "linux")]
+    use nix::sys::eventfd::{eventfd, EfdFlags};
+    #[cfg(target_os = "linux")]
+    use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+    use nix::unistd::{close, dup, fork, read, write, ForkResult};
+
+    mod request;
+    pub use request::{RecvRequest, SendRequest};
+    #[cfg(feature = "serde")]
+    mod ser;
+    #[cfg(feature = "serde")]
+    pub use ser::{SerReceiver, SerSender};
 
     const SENDER: u8 = 0;
     const RECEIVER: u8 = 1;
 
+    pub(crate) fn nix_to_io(e: nix::Error) -> io::Error {
+        match e {
+            nix::Error::Sys(errno) => io::Error::from_raw_os_error(errno as i32),
+            e => io::Error::other(e),
+        }
+    }
+
+    /// Marker for types that can be sent through a channel by copying their bytes.
+    ///
+    /// The receiving process interprets the bytes in its own address space, so any pointer or
+    /// reference in a message would point into the wrong memory after the transfer. Types
+    /// without this marker - like `*const u8` or `&'static str` - are rejected at compile time.
+    ///
+    /// # Safety
+    ///
+    /// Implementors must not contain raw pointers, references, function pointers or anything
+    /// else whose meaning depends on the address space of the sending process.
+    pub unsafe trait SharedSafe: Copy {}
+
+    macro_rules! impl_shared_safe {
+        ($($t:ty),*) => {
+            $(unsafe impl SharedSafe for $t {})*
+        };
+    }
+
+    impl_shared_safe!(
+        (),
+        bool,
+        char,
+        u8,
+        u16,
+        u32,
+        u64,
+        u128,
+        usize,
+        i8,
+        i16,
+        i32,
+        i64,
+        i128,
+        isize,
+        f32,
+        f64
+    );
+
+    unsafe impl<T: SharedSafe, const N: usize> SharedSafe for [T; N] {}
+    unsafe impl<A: SharedSafe, B: SharedSafe> SharedSafe for (A, B) {}
+    unsafe impl<A: SharedSafe, B: SharedSafe, C: SharedSafe> SharedSafe for (A, B, C) {}
+    unsafe impl<A: SharedSafe, B: SharedSafe, C: SharedSafe, D: SharedSafe> SharedSafe
+        for (A, B, C, D)
+    {
+    }
+
+    /// How the two ends of a channel wait for each other
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum WaitPolicy {
+        /// Busy-wait on the owner flag. Lowest latency, but keeps a core busy while waiting.
+        Spin,
+        /// Spin for a short while, then sleep on a futex until the other side hands over
+        /// ownership. Close to `Spin` under steady traffic without burning CPU while idle.
+        /// Platforms without futexes nap for short intervals instead.
+        #[default]
+        Futex,
+        /// Sleep in `read` on an eventfd until the other side hands over ownership. Slower
+        /// to wake up, but a blocked process doesn't use any CPU. Linux only, creating a
+        /// channel with this policy fails elsewhere.
+        EventFd,
+        /// Spin for a while, then yield the core to other processes, then nap for short
+        /// intervals. Made for running more ranks than there are cores, where spinning keeps
+        /// the peer from getting CPU time to hand over ownership.
+        SpinThenYield(Backoff),
+    }
+
+    /// Thresholds of `WaitPolicy::SpinThenYield`, counted in checks of the owner flag
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Backoff {
+        /// Checks with a `spin_loop` hint in between
+        pub spins: u32,
+        /// Checks with `std::thread::yield_now` in between once spinning didn't succeed.
+        /// Afterwards the waiting process naps for `BACKOFF_NAP` between checks.
+        pub yields: u32,
+    }
+
+    impl Default for Backoff {
+        fn default() -> Self {
+            Backoff {
+                spins: 100,
+                yields: 100,
+            }
+        }
+    }
+
+    /// Where the owner flag of a transfer buffer sits relative to the payload
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum Layout {
+        /// Payload at offset 0, owner flag behind it
+        #[default]
+        OwnerLast,
+        /// Owner flag at offset 0, payload behind it - for systems expecting a header
+        OwnerFirst,
+    }
+
+    /// Settings for creating a channel
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct ChannelOptions {
+        pub wait_policy: WaitPolicy,
+        pub layout: Layout,
+        /// Protect every message sent with `Sender::send` by a CRC-32, so that `recv` notices
+        /// corrupted messages instead of returning them. Costs a pass over the message on
+        /// both ends.
+        pub checksum: bool,
+    }
+
+    /// Part of a transfer buffer that coordinates access to the payload
+    #[repr(C)]
+    struct Control {
+        /// Id of the side that may currently access the payload. A full word so it can be
+        /// used as a futex.
+        owner: AtomicU32,
+        /// Number of processes sleeping on the futex, so handing over ownership only needs a
+        /// syscall if somebody actually has to be woken up
+        waiters: AtomicU32,
+        /// Tag of the message currently in the buffer, see `Sender::send_tagged`
+        tag: AtomicU32,
+        /// Set by `Sender::close` instead of sending another message
+        closed: AtomicU32,
+        /// CRC-32 of the message currently in the buffer, for `ChannelOptions::checksum`
+        checksum: AtomicU32,
+    }
+
+    /// How often `WaitPolicy::Futex` checks the owner flag before going to sleep
+    const FUTEX_SPINS: usize = 1000;
+
+    /// How long `WaitPolicy::SpinThenYield` sleeps between checks after its spins and yields
+    const BACKOFF_NAP: Duration = Duration::from_micros(50);
+
+    /// Sleeps while `word` holds `expected`, at most for `timeout`. Errors don't matter since
+    /// callers check the word again anyway: `EAGAIN` means it already changed.
+    #[cfg(target_os = "linux")]
+    fn futex_wait(word: &AtomicU32, expected: u32, timeout: Option<Duration>) {
+        use nix::libc::{c_long, syscall, time_t, timespec, SYS_futex, FUTEX_WAIT};
+        let timeout = timeout.map(|t| timespec {
+            tv_sec: t.as_secs() as time_t,
+            tv_nsec: t.subsec_nanos() as c_long,
+        });
+        let timeout_ptr = timeout
+            .as_ref()
+            .map_or(std::ptr::null(), |t| t as *const timespec);
+        // no FUTEX_PRIVATE_FLAG, the word is shared with other processes
+        unsafe {
+            syscall(
+                SYS_futex,
+                word as *const AtomicU32,
+                FUTEX_WAIT,
+                expected,
+                timeout_ptr,
+            )
+        };
+    }
+
+    /// Wakes up all processes sleeping on `word`
+    #[cfg(target_os = "linux")]
+    fn futex_wake(word: &AtomicU32) {
+        use nix::libc::{syscall, SYS_futex, FUTEX_WAKE};
+        unsafe { syscall(SYS_futex, word as *const AtomicU32, FUTEX_WAKE, i32::MAX) };
+    }
+
+    /// Without futexes there is nothing to sleep on until the word changes, so just nap
+    /// briefly before the caller checks again
+    #[cfg(not(target_os = "linux"))]
+    fn futex_wait(_word: &AtomicU32, _expected: u32, timeout: Option<Duration>) {
+        let nap = Duration::from_micros(50);
+        std::thread::sleep(timeout.map_or(nap, |t| t.min(nap)));
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn futex_wake(_word: &AtomicU32) {}
+
+    /// An unnamed file in memory that can be mapped more than once
+    #[cfg(target_os = "linux")]
+    fn shared_memory_file() -> io::Result<File> {
+        let name = CString::new("mpi2-channel").unwrap();
+        let fd = memfd_create(&name, MemFdCreateFlag::MFD_CLOEXEC).map_err(nix_to_io)?;
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    /// An unnamed file in memory that can be mapped more than once. Without memfds this is a
+    /// POSIX shared memory object that is unlinked again right away.
+    #[cfg(not(target_os = "linux"))]
+    fn shared_memory_file() -> io::Result<File> {
+        use nix::fcntl::OFlag;
+        use nix::sys::mman::{shm_open, shm_unlink};
+        use nix::sys::stat::Mode;
+
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        // short enough for the 31 characters macOS allows
+        let name = format!(
+            "/mpi2.{}.{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let fd = shm_open(
+            name.as_str(),
+            OFlag::O_RDWR | OFlag::O_CREAT | OFlag::O_EXCL,
+            Mode::S_IRUSR | Mode::S_IWUSR,
+        )
+        .map_err(nix_to_io)?;
+        let _ = shm_unlink(name.as_str());
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    /// What a named buffer was created for. Stored behind its control block, so that only
+    /// senders of the same message type can attach.
+    #[derive(Debug, Clone, Copy)]
+    struct MessageType {
+        size: u64,
+        align: u64,
+        /// FNV-1a hash of the type name, to tell apart types of the same size and alignment
+        fingerprint: u64,
+        /// Only for error messages, not stored
+        name: &'static str,
+    }
+
+    impl PartialEq for MessageType {
+        fn eq(&self, other: &Self) -> bool {
+            (self.size, self.align, self.fingerprint)
+                == (other.size, other.align, other.fingerprint)
+        }
+    }
+
+    impl MessageType {
+        const LEN: usize = 3 * size_of::<u64>();
+
+        fn of<T>() -> Self {
+            let name = std::any::type_name::<T>();
+            let fingerprint = name.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+            });
+            MessageType {
+                size: size_of::<T>() as u64,
+                align: align_of::<T>() as u64,
+                fingerprint,
+                name,
+            }
+        }
+
+        fn to_bytes(self) -> [u8; Self::LEN] {
+            let mut bytes = [0; Self::LEN];
+            for (chunk, field) in bytes
+                .chunks_mut(size_of::<u64>())
+                .zip([self.size, self.align, self.fingerprint].iter())
+            {
+                chunk.copy_from_slice(&field.to_ne_bytes());
+            }
+            bytes
+        }
+
+        fn from_bytes(bytes: &[u8]) -> Self {
+            let mut fields = bytes.chunks(size_of::<u64>()).map(|chunk| {
+                let mut field = [0; size_of::<u64>()];
+                field.copy_from_slice(chunk);
+                u64::from_ne_bytes(field)
+            });
+            MessageType {
+                size: fields.next().unwrap(),
+                align: fields.next().unwrap(),
+                fingerprint: fields.next().unwrap(),
+                name: "",
+            }
+        }
+    }
+
+    /// CRC-32 as used by zlib and Ethernet, bit by bit - tables aren't worth it for the rare
+    /// channel that wants a checksum
+    fn crc32(data: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for &byte in data {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+            }
+        }
+        !crc
+    }
+
+    /// What a transfer buffer uses to implement its `WaitPolicy`
+    #[derive(Debug)]
+    enum Waker {
+        Spin,
+        Futex,
+        /// eventfds signaled on handover to the sender and the receiver, indexed by owner id
+        EventFd([RawFd; 2]),
+        Backoff(Backoff),
+    }
+
+    impl Waker {
+        fn new(wait_policy: WaitPolicy) -> io::Result<Self> {
+            Ok(match wait_policy {
+                WaitPolicy::Spin => Waker::Spin,
+                WaitPolicy::Futex => Waker::Futex,
+                WaitPolicy::SpinThenYield(backoff) => Waker::Backoff(backoff),
+                #[cfg(target_os = "linux")]
+                WaitPolicy::EventFd => {
+                    let to_sender = eventfd(0, EfdFlags::EFD_CLOEXEC).map_err(nix_to_io)?;
+                    let to_receiver = eventfd(0, EfdFlags::EFD_CLOEXEC).map_err(|e| {
+                        let _ = close(to_sender);
+                        nix_to_io(e)
+                    })?;
+                    Waker::EventFd([to_sender, to_receiver])
+                }
+                #[cfg(not(target_os = "linux"))]
+                WaitPolicy::EventFd => {
+                    return Err(Error::other(
+                        "WaitPolicy::EventFd is only available on Linux",
+                    ))
+                }
+            })
+        }
+
+        /// Another waker signaling the same eventfds, with its own descriptors to close
+        fn try_clone(&self) -> io::Result<Self> {
+            Ok(match *self {
+                Waker::Spin => Waker::Spin,
+                Waker::Futex => Waker::Futex,
+                Waker::Backoff(backoff) => Waker::Backoff(backoff),
+                Waker::EventFd([to_sender, to_receiver]) => {
+                    let to_sender = dup(to_sender).map_err(nix_to_io)?;
+                    let to_receiver = dup(to_receiver).map_err(|e| {
+                        let _ = close(to_sender);
+                        nix_to_io(e)
+                    })?;
+                    Waker::EventFd([to_sender, to_receiver])
+                }
+            })
+        }
+    }
+
+    impl Drop for Waker {
+        fn drop(&mut self) {
+            if let Waker::EventFd(events) = *self {
+                for &fd in events.iter() {
+                    let _ = close(fd);
+                }
+            }
+        }
+    }
+
     #[derive(Debug)]
     struct TransferBuffer {
         mmap: MmapMut,
+        size: usize,
+        layout: Layout,
+        /// Whether messages carry a checksum, see `ChannelOptions::checksum`
+        checksum: bool,
+        waker: Waker,
+        /// The shared memory behind `mmap` for buffers that can grow, see `grow`
+        file: Option<File>,
     }
 
     impl TransferBuffer {
-        pub fn new(size: usize, owner: u8) -> io::Result<Self> {
-            let mut mmap_options = MmapOptions::new();
-            mmap_options
-                .len(size + 2)
-                .map_anon()
-                .map(|mmap| TransferBuffer { mmap })
-                .map(|mut buf| {
-                    buf.write_owner(owner);
-                    buf
-                })
+        pub fn new(size: usize, owner: u8, options: ChannelOptions) -> io::Result<Self> {
+            // the mapping is zeroed, which is a valid `Control`
+            let mmap = MmapOptions::new()
+                .len(Self::mapping_len(size, options.layout))
+                .map_anon()?;
+            let mut buf = TransferBuffer {
+                mmap,
+                size,
+                layout: options.layout,
+                checksum: options.checksum,
+                waker: Waker::new(options.wait_policy)?,
+                file: None,
+            };
+            buf.write_owner(owner);
+            Ok(buf)
         }
 
-        fn owner(&self) -> *const u8 {
-            &self.mmap[self.size()]
+        /// Creates a buffer that `grow` can enlarge later on. Its control block comes first, so
+        /// that it stays in place when the payload grows.
+        ///
+        /// Only on Linux, where the memfd can be resized any number of times. The POSIX shared
+        /// memory objects used elsewhere can only be sized once on macOS.
+        #[cfg(target_os = "linux")]
+        pub fn growable(size: usize, owner: u8, wait_policy: WaitPolicy) -> io::Result<Self> {
+            let layout = Layout::OwnerFirst;
+            let len = Self::mapping_len(size, layout);
+            let file = shared_memory_file()?;
+            // the file is zero filled, which is a valid `Control`
+            file.set_len(len as u64)?;
+            let mut buf = TransferBuffer {
+                mmap: unsafe { MmapOptions::new().len(len).map_mut(&file)? },
+                size,
+                layout,
+                checksum: false,
+                waker: Waker::new(wait_policy)?,
+                file: Some(file),
+            };
+            buf.write_owner(owner);
+            Ok(buf)
         }
 
-        fn buffer(&self) -> &[u8] {
-            &self.mmap[..self.size() - 1]
+        /// Whether `grow` can enlarge the buffer
+        fn can_grow(&self) -> bool {
+            self.file.is_some()
+        }
+
+        /// Enlarges the payload to `size` bytes, by growing the shared memory if the other end
+        /// didn't already, and mapping it again. The payload keeps its contents. Only call
+        /// this while owning the buffer, the other end has to grow its mapping before it
+        /// accesses the larger payload.
+        fn grow(&mut self, size: usize) -> io::Result<()> {
+            let file = match &self.file {
+                Some(file) if size > self.size => file,
+                _ => return Ok(()),
+            };
+            let len = Self::mapping_len(size, self.layout);
+            if file.metadata()?.len() < len as u64 {
+                file.set_len(len as u64)?;
+            }
+            self.mmap = unsafe { MmapOptions::new().len(len).map_mut(file)? };
+            self.size = size;
+            Ok(())
+        }
+
+        /// Creates two buffers mapping the same shared memory, so that each end of a channel
+        /// can own one instead of borrowing it from the other end.
+        pub fn pair(size: usize, owner: u8, options: ChannelOptions) -> io::Result<(Self, Self)> {
+            let len = Self::mapping_len(size, options.layout);
+            // an anonymous mapping can't be mapped a second time, a file can
+            let file = shared_memory_file()?;
+            // the file is zero filled, which is a valid `Control`
+            file.set_len(len as u64)?;
+            let map = || unsafe { MmapOptions::new().len(len).map_mut(&file) };
+
+            let waker = Waker::new(options.wait_policy)?;
+            let first = TransferBuffer {
+                mmap: map()?,
+                size,
+                layout: options.layout,
+                checksum: options.checksum,
+                waker: waker.try_clone()?,
+                file: None,
+            };
+            let mut second = TransferBuffer {
+                mmap: map()?,
+                size,
+                layout: options.layout,
+                checksum: options.checksum,
+                waker,
+                file: None,
+            };
+            second.write_owner(owner);
+            Ok((first, second))
+        }
+
+        /// Creates a buffer for messages of type `ty` backed by the file at `path`, which any
+        /// process can attach to with `open_named` - not just ones forked from this one. An
+        /// existing file is replaced. Both ends of a named channel use the default
+        /// `ChannelOptions`.
+        pub fn create_named(path: &Path, ty: MessageType, owner: u8) -> io::Result<Self> {
+            let size = ty.size as usize;
+            let header = Self::mapping_len(size, Layout::default());
+            let len = header + MessageType::LEN;
+            // set up the file under a temporary name and move it into place afterwards, so
+            // nobody can attach before it has its full size and an owner
+            let mut tmp_path = path.as_os_str().to_owned();
+            tmp_path.push(format!(".{}.tmp", std::process::id()));
+            let create = || {
+                let file = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(&tmp_path)?;
+                file.set_len(len as u64)?;
+                let mut buf = TransferBuffer {
+                    mmap: unsafe { MmapOptions::new().len(len).map_mut(&file)? },
+                    size,
+                    layout: Layout::default(),
+                    checksum: false,
+                    waker: Waker::new(WaitPolicy::default())?,
+                    file: None,
+                };
+                buf.mmap[header..].copy_from_slice(&ty.to_bytes());
+                buf.write_owner(owner);
+                std::fs::rename(&tmp_path, path)?;
+                Ok(buf)
+            };
+            create().inspect_err(|_| {
+                let _ = std::fs::remove_file(&tmp_path);
+            })
+        }
+
+        /// Attaches to a buffer created by `create_named`. Leaves the owner alone, so a message
+        /// that is already in the buffer stays there. Fails with `ErrorKind::InvalidData` if
+        /// the buffer was created for another message type.
+        pub fn open_named(path: &Path, ty: MessageType) -> io::Result<Self> {
+            let size = ty.size as usize;
+            let header = Self::mapping_len(size, Layout::default());
+            let len = header + MessageType::LEN;
+            let mismatch = || {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{} isn't a channel for {}", path.display(), ty.name),
+                )
+            };
+            let file = OpenOptions::new().read(true).write(true).open(path)?;
+            if file.metadata()?.len() != len as u64 {
+                return Err(mismatch());
+            }
+            let mmap = unsafe { MmapOptions::new().len(len).map_mut(&file)? };
+            if MessageType::from_bytes(&mmap[header..]) != ty {
+                return Err(mismatch());
+            }
+            Ok(TransferBuffer {
+                mmap,
+                size,
+                layout: Layout::default(),
+                checksum: false,
+                waker: Waker::new(WaitPolicy::default())?,
+                file: None,
+            })
         }
 
-        fn owner_mut(&mut self) -> *mut u8 {
-            let i = self.size();
-            &mut self.mmap[i]
+        fn mapping_len(size: usize, layout: Layout) -> usize {
+            let payload = match layout {
+                Layout::OwnerLast => size.next_multiple_of(align_of::<Control>()),
+                Layout::OwnerFirst => size,
+            };
+            payload + size_of::<Control>()
+        }
+
+        fn control_offset(&self) -> usize {
+            match self.layout {
+                Layout::OwnerLast => self.size.next_multiple_of(align_of::<Control>()),
+                Layout::OwnerFirst => 0,
+            }
+        }
+
+        fn payload_range(&self) -> Range<usize> {
+            match self.layout {
+                Layout::OwnerLast => 0..self.size,
+                Layout::OwnerFirst => size_of::<Control>()..size_of::<Control>() + self.size,
+            }
+        }
+
+        fn control(&self) -> &Control {
+            // the mapping is page aligned and the offset a multiple of the alignment
+            unsafe { &*(self.mmap.as_ptr().add(self.control_offset()) as *const Control) }
+        }
+
+        fn buffer(&self) -> &[u8] {
+            &self.mmap[self.payload_range()]
         }
 
         fn buffer_mut(&mut self) -> &mut [u8] {
-            let i = self.size();
-            &mut self.mmap[..i - 1]
+            let range = self.payload_range();
+            &mut self.mmap[range]
         }
 
         /// Returns the size of the data buffer
         fn size(&self) -> usize {
-            self.mmap.len() - 1
+            self.size
         }
 
         pub fn write_owner(&mut self, owner_id: u8) {
-            unsafe { self.owner_mut().write_volatile(owner_id) }
+            let control = self.control();
+            // SeqCst so that either we see the waiter registered in `futex_wait_for_owner`
+            // or the waiter sees the new owner before going to sleep
+            control.owner.store(owner_id as u32, Ordering::SeqCst);
+            match self.waker {
+                Waker::Spin | Waker::Backoff(_) => {}
+                Waker::Futex => {
+                    if control.waiters.load(Ordering::SeqCst) > 0 {
+                        futex_wake(&control.owner);
+                    }
+                }
+                Waker::EventFd(events) => {
+                    // bumps the eventfd counter, waking up the new owner if it's blocked in read
+                    let _ = write(events[owner_id as usize], &1u64.to_ne_bytes());
+                }
+            }
         }
 
         pub fn current_owner(&self) -> u8 {
-            unsafe { self.owner().read_volatile() }
+            self.control().owner.load(Ordering::Acquire) as u8
+        }
+
+        /// Only meaningful while owning the buffer, the handover orders it with the payload
+        fn tag(&self) -> u32 {
+            self.control().tag.load(Ordering::Relaxed)
+        }
+
+        fn set_tag(&self, tag: u32) {
+            self.control().tag.store(tag, Ordering::Relaxed)
+        }
+
+        /// Stores the checksum of the first `len` bytes of the payload if checksums are enabled
+        fn seal(&self, len: usize) {
+            if self.checksum {
+                let crc = crc32(&self.buffer()[..len]);
+                self.control().checksum.store(crc, Ordering::Relaxed);
+            }
+        }
+
+        /// Fails with `ErrorKind::InvalidData` if checksums are enabled and the first `len`
+        /// bytes of the payload don't match the checksum `seal` stored
+        fn verify(&self, len: usize) -> io::Result<()> {
+            if self.checksum
+                && crc32(&self.buffer()[..len]) != self.control().checksum.load(Ordering::Relaxed)
+            {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "message checksum mismatch",
+                ));
+            }
+            Ok(())
+        }
+
+        /// Whether the sender closed the channel, only meaningful while owning the buffer
+        fn closed(&self) -> bool {
+            self.control().closed.load(Ordering::Relaxed) != 0
         }
 
         pub fn wait_for_owner(&self, owner_id: u8) -> &Self {
-            self.current_owner();
-            while self.current_owner() != owner_id {}
+            match self.waker {
+                Waker::Spin => while self.current_owner() != owner_id {},
+                Waker::Futex => {
+                    self.futex_wait_for_owner(owner_id, None);
+                }
+                Waker::Backoff(backoff) => {
+                    self.backoff_wait_for_owner(owner_id, backoff, None);
+                }
+                Waker::EventFd(events) => {
+                    let mut counter = [0; 8];
+                    while self.current_owner() != owner_id {
+                        // a read resets the counter, so a signal left over from an earlier
+                        // handover costs at most one extra iteration
+                        let _ = read(events[owner_id as usize], &mut counter);
+                    }
+                }
+            }
             self
         }
+
+        /// Like `wait_for_owner`, but gives up once `timeout` has passed. Returns whether
+        /// `owner_id` got ownership of the buffer in time.
+        pub fn wait_for_owner_timeout(&self, owner_id: u8, timeout: Duration) -> io::Result<bool> {
+            let deadline = Instant::now() + timeout;
+            match self.waker {
+                Waker::Spin => {
+                    while self.current_owner() != owner_id {
+                        if Instant::now() >= deadline {
+                            return Ok(false);
+                        }
+                    }
+                }
+                Waker::Futex => return Ok(self.futex_wait_for_owner(owner_id, Some(deadline))),
+                Waker::Backoff(backoff) => {
+                    return Ok(self.backoff_wait_for_owner(owner_id, backoff, Some(deadline)))
+                }
+                Waker::EventFd(events) => {
+                    let fd = events[owner_id as usize];
+                    let mut counter = [0; 8];
+                    while self.current_owner() != owner_id {
+                        let remaining = deadline.saturating_duration_since(Instant::now());
+                        if remaining == Duration::ZERO {
+                            return Ok(false);
+                        }
+                        // round up, poll would otherwise return early for sub-millisecond rests
+                        let millis = remaining.as_micros().div_ceil(1000);
+                        let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+                        match poll(&mut fds, millis.min(i32::MAX as u128) as i32) {
+                            Ok(0) => {}
+                            Ok(_) => {
+                                let _ = read(fd, &mut counter);
+                            }
+                            Err(nix::Error::Sys(Errno::EINTR)) => {}
+                            Err(e) => return Err(nix_to_io(e)),
+                        }
+                    }
+                }
+            }
+            Ok(true)
+        }
+
+        /// Resets the eventfd signaling handovers to `owner_id`, without blocking. Call it
+        /// before checking the owner flag, so that a handover racing with the check leaves the
+        /// eventfd set.
+        fn clear_events(&self, owner_id: u8) {
+            if let Waker::EventFd(events) = self.waker {
+                let fd = events[owner_id as usize];
+                let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+                if let Ok(1) = poll(&mut fds, 0) {
+                    let _ = read(fd, &mut [0; 8]);
+                }
+            }
+        }
+
+        /// Spins, yields and naps as configured by `backoff` until `owner_id` owns the buffer
+        /// or the deadline passed. Returns whether `owner_id` got ownership.
+        fn backoff_wait_for_owner(
+            &self,
+            owner_id: u8,
+            backoff: Backoff,
+            deadline: Option<Instant>,
+        ) -> bool {
+            let mut checks: u64 = 0;
+            while self.current_owner() != owner_id {
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    return false;
+                }
+                if checks < backoff.spins as u64 {
+                    spin_loop();
+                } else if checks < backoff.spins as u64 + backoff.yields as u64 {
+                    std::thread::yield_now();
+                } else {
+                    std::thread::sleep(BACKOFF_NAP);
+                }
+                checks += 1;
+            }
+            true
+        }
+
+        /// Spins briefly, then sleeps on the owner futex until `owner_id` owns the buffer or
+        /// the deadline passed. Returns whether `owner_id` got ownership.
+        fn futex_wait_for_owner(&self, owner_id: u8, deadline: Option<Instant>) -> bool {
+            let control = self.control();
+            let owner_id = owner_id as u32;
+            for _ in 0..FUTEX_SPINS {
+                if control.owner.load(Ordering::Acquire) == owner_id {
+                    return true;
+                }
+                spin_loop();
+            }
+            loop {
+                let timeout = match deadline {
+                    Some(deadline) => {
+                        let remaining = deadline.saturating_duration_since(Instant::now());
+                        if remaining == Duration::ZERO {
+                            return control.owner.load(Ordering::Acquire) == owner_id;
+                        }
+                        Some(remaining)
+                    }
+                    None => None,
+                };
+                control.waiters.fetch_add(1, Ordering::SeqCst);
+                let current = control.owner.load(Ordering::SeqCst);
+                if current == owner_id {
+                    control.waiters.fetch_sub(1, Ordering::SeqCst);
+                    return true;
+                }
+                // the kernel only puts us to sleep if the owner is still `current`, so a
+                // handover right before this call isn't missed
+                futex_wait(&control.owner, current, timeout);
+                control.waiters.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
     }
 
     impl Write for TransferBuffer {
@@ -93,22 +834,144 @@ mod channel {
         }
     }
 
+    /// What a `Sender` writes to
+    #[derive(Debug)]
+    enum SenderBuffer<'a> {
+        /// The buffer of the `Receiver` the sender was created from
+        Borrowed(&'a mut TransferBuffer),
+        /// A mapping of its own, see `channel`
+        Owned(TransferBuffer),
+    }
+
+    impl SenderBuffer<'_> {
+        fn get(&self) -> &TransferBuffer {
+            match self {
+                SenderBuffer::Borrowed(buffer) => buffer,
+                SenderBuffer::Owned(buffer) => buffer,
+            }
+        }
+
+        fn get_mut(&mut self) -> &mut TransferBuffer {
+            match self {
+                SenderBuffer::Borrowed(buffer) => buffer,
+                SenderBuffer::Owned(buffer) => buffer,
+            }
+        }
+    }
+
+    /// Why sending or receiving on a channel failed
+    #[derive(Debug)]
+    pub enum ChannelError {
+        /// The channel's buffer couldn't be accessed
+        Io(io::Error),
+        /// The buffer can't hold a message of the channel's type
+        BufferTooSmall { needed: usize, available: usize },
+        /// The sender closed the channel and all messages have been received
+        Closed,
+        /// `Receiver::recv_large_timeout` timed out in the middle of a payload
+        Partial(PartialTransfer),
+    }
+
+    /// What arrived of a payload from `Sender::send_bytes` before a timeout. The rest stays
+    /// in the channel, `Receiver::resume_large_timeout` picks it up.
+    #[derive(Debug)]
+    pub struct PartialTransfer {
+        /// The bytes received so far
+        pub received: Vec<u8>,
+        /// Length of the whole payload
+        pub total: usize,
+        /// Number of chunks `received` arrived in
+        pub chunks: usize,
+    }
+
+    impl fmt::Display for ChannelError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                ChannelError::Io(e) => write!(f, "channel buffer not accessible: {}", e),
+                ChannelError::BufferTooSmall { needed, available } => write!(
+                    f,
+                    "message of {} bytes doesn't fit into buffer of {} bytes",
+                    needed, available
+                ),
+                ChannelError::Closed => write!(f, "channel closed by the sender"),
+                ChannelError::Partial(partial) => write!(
+                    f,
+                    "timed out after receiving {} of {} bytes",
+                    partial.received.len(),
+                    partial.total
+                ),
+            }
+        }
+    }
+
+    impl std::error::Error for ChannelError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                ChannelError::Io(e) => Some(e),
+                ChannelError::BufferTooSmall { .. }
+                | ChannelError::Closed
+                | ChannelError::Partial(_) => None,
+            }
+        }
+    }
+
+    impl From<io::Error> for ChannelError {
+        fn from(e: io::Error) -> Self {
+            ChannelError::Io(e)
+        }
+    }
+
+    /// Makes sure a `T` can be copied in and out of `buffer` without going out of bounds
+    fn check_size<T>(buffer: &TransferBuffer) -> Result<(), ChannelError> {
+        if buffer.size() < size_of::<T>() {
+            return Err(ChannelError::BufferTooSmall {
+                needed: size_of::<T>(),
+                available: buffer.size(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Callback for instrumenting a channel end, gets the size of a message in bytes and the
+    /// time it was transferred
+    struct Hook(Box<dyn Fn(usize, Instant) + Send>);
+
+    impl Hook {
+        fn call(&self, size: usize) {
+            (self.0)(size, Instant::now())
+        }
+    }
+
+    impl fmt::Debug for Hook {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("Hook")
+        }
+    }
+
     #[derive(Debug)]
     pub struct Sender<'a, T> {
-        buffer: UnsafeCell<&'a mut TransferBuffer>,
+        buffer: UnsafeCell<SenderBuffer<'a>>,
+        on_send: Option<Hook>,
         phantom_data: PhantomData<T>,
     }
 
     impl<'a, T> Sender<'a, T> {
-        fn get_buffer_ref(&self) -> io::Result<&'a TransferBuffer> {
+        /// Installs a callback that is called after every message this end sent, with the
+        /// size of the message and the time it was handed over. `Write` calls it once per
+        /// frame. Without a hook sending costs nothing extra.
+        pub fn set_on_send(&mut self, hook: impl Fn(usize, Instant) + Send + 'static) {
+            self.on_send = Some(Hook(Box::new(hook)));
+        }
+
+        fn get_buffer_ref(&self) -> io::Result<&TransferBuffer> {
             unsafe { self.buffer.get().as_ref() }
-                .map(|x| &**x)
+                .map(|x| x.get())
                 .ok_or_else(|| Error::new(ErrorKind::Other, "Failed to get reference to buffer"))
         }
 
-        fn get_buffer_mut(&mut self) -> io::Result<&'a mut TransferBuffer> {
+        fn get_buffer_mut(&mut self) -> io::Result<&mut TransferBuffer> {
             unsafe { self.buffer.get().as_mut() }
-                .map(|x| &mut **x)
+                .map(|x| x.get_mut())
                 .ok_or_else(|| {
                     Error::new(
                         ErrorKind::Other,
@@ -116,26 +979,143 @@ mod channel {
                     )
                 })
         }
+    }
+
+    impl<T> Sender<'_, T> {
+        /// Sends a payload of any length: first a frame holding the length, then the payload in
+        /// as many frames as needed. Pairs with `Receiver::recv_bytes`.
+        ///
+        /// A buffer created by `Receiver::growable`, which is Linux only, first grows to fit all
+        /// of `data`, so the payload then takes a single frame.
+        ///
+        /// Fails with `ChannelError::BufferTooSmall` if the buffer can't hold the length prefix.
+        pub fn send_bytes(&mut self, data: &[u8]) -> Result<(), ChannelError> {
+            let buf = self.get_buffer_mut()?;
+            check_size::<u64>(buf)?;
+            buf.wait_for_owner(SENDER);
+            // the receiver sees the length and grows its own mapping to match
+            buf.grow(data.len())?;
+            buf.buffer_mut()[..size_of::<u64>()]
+                .copy_from_slice(&(data.len() as u64).to_ne_bytes());
+            buf.write_owner(RECEIVER);
+            for chunk in data.chunks(buf.size()) {
+                buf.wait_for_owner(SENDER);
+                buf.buffer_mut()[..chunk.len()].copy_from_slice(chunk);
+                buf.write_owner(RECEIVER);
+            }
+            Ok(())
+        }
+
+        /// Ends the stream: once the receiver got all messages sent before, receiving fails
+        /// with `ChannelError::Closed` and `Read` returns end of file instead of blocking.
+        ///
+        /// Blocks until the receiver picked up the last message, so a process can exit right
+        /// after closing without its peer waiting forever for more.
+        pub fn close(mut self) -> Result<(), ChannelError> {
+            let buf = self.get_buffer_mut()?;
+            buf.wait_for_owner(SENDER);
+            buf.control().closed.store(1, Ordering::Relaxed);
+            // the buffer stays with the receiver from now on
+            buf.write_owner(RECEIVER);
+            Ok(())
+        }
+    }
+
+    impl<T: SharedSafe> Sender<'static, T> {
+        /// Connects to the channel a `Receiver::new_named` created at `path`, possibly in
+        /// an unrelated process. Fails with `ErrorKind::NotFound` if there is no channel yet,
+        /// and with `ErrorKind::InvalidData` if it was created for another message type.
+        ///
+        /// Connecting doesn't touch the channel state, so a message sent by an earlier sender
+        /// that hasn't been received yet isn't lost.
+        pub fn connect_named<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+            let buffer = TransferBuffer::open_named(path.as_ref(), MessageType::of::<T>())?;
+            Ok(Sender {
+                buffer: UnsafeCell::new(SenderBuffer::Owned(buffer)),
+                on_send: None,
+                phantom_data: PhantomData,
+            })
+        }
+    }
 
-        fn write_unaligned(&mut self, src: T) {
-            let ptr = self.get_buffer_mut().unwrap().buffer_mut().as_mut_ptr() as *mut T;
-            unsafe { ptr.write_unaligned(src) }
+    impl<'a, T: SharedSafe> Sender<'a, T> {
+        fn write_unaligned(&mut self, src: T) -> Result<(), ChannelError> {
+            let buf = self.get_buffer_mut()?;
+            check_size::<T>(buf)?;
+            let ptr = buf.buffer_mut().as_mut_ptr() as *mut T;
+            debug_assert!(buf.size() >= size_of::<T>());
+            unsafe { ptr.write_unaligned(src) };
+            Ok(())
         }
 
         /// Put data into the channel
-        pub fn send(&mut self, data: T) {
-            self.get_buffer_ref().unwrap().wait_for_owner(SENDER);
-            self.write_unaligned(data);
-            self.get_buffer_mut().unwrap().write_owner(RECEIVER);
+        pub fn send(&mut self, data: T) -> Result<(), ChannelError> {
+            self.send_tagged(0, data)
+        }
+
+        /// Like `send`, but panics on failure - for benchmarks that don't want to deal with
+        /// errors in their hot loop
+        pub fn send_unchecked(&mut self, data: T) {
+            self.send(data).expect("Failed to send");
+        }
+
+        /// Like `send`, but gives up if the receiver didn't pick up the previous message
+        /// within `timeout`, e.g. because it died. Hands `data` back on failure, so it can be
+        /// sent again later. Nothing is written to the channel then.
+        pub fn send_timeout(&mut self, data: T, timeout: Duration) -> Result<(), T> {
+            let ready = match self.get_buffer_ref() {
+                Ok(buf) => {
+                    check_size::<T>(buf).is_ok()
+                        && buf.wait_for_owner_timeout(SENDER, timeout).unwrap_or(false)
+                }
+                Err(_) => false,
+            };
+            if !ready {
+                return Err(data);
+            }
+            // owning the buffer already, so this doesn't block
+            self.send(data).map_err(|_| data)
+        }
+
+        /// Sends `data` if the receiver already picked up the previous message, without ever
+        /// blocking. Otherwise hands `data` back right away and leaves the channel alone, e.g.
+        /// to drop a reading that would be stale by the time it's received.
+        pub fn try_send(&mut self, data: T) -> Result<(), T> {
+            match self.get_buffer_ref() {
+                Ok(buf) if buf.current_owner() == SENDER => {}
+                _ => return Err(data),
+            }
+            self.send(data).map_err(|_| data)
+        }
+
+        /// Put data into the channel together with a `tag` the receiver gets back from
+        /// `Receiver::recv_tagged`, e.g. to tell apart where a message came from. `send` uses
+        /// a tag of 0.
+        pub fn send_tagged(&mut self, tag: u32, data: T) -> Result<(), ChannelError> {
+            self.get_buffer_ref()?.wait_for_owner(SENDER);
+            self.write_unaligned(data)?;
+            let buf = self.get_buffer_mut()?;
+            buf.seal(size_of::<T>());
+            buf.set_tag(tag);
+            buf.write_owner(RECEIVER);
+            if let Some(hook) = &self.on_send {
+                hook.call(size_of::<T>());
+            }
+            Ok(())
         }
     }
 
     impl<T> Write for Sender<'_, T> {
+        /// Writes one frame of at most the buffer size, so `write_all` can spread larger
+        /// payloads over multiple frames.
         fn write(&mut self, data: &[u8]) -> io::Result<usize> {
             self.get_buffer_ref()?.wait_for_owner(SENDER);
             let buf = self.get_buffer_mut()?;
-            let w = (&mut buf.buffer_mut()[..data.len()]).write(data)?;
+            let w = buf.buffer_mut().write(data)?;
             buf.write_owner(RECEIVER);
+            if let Some(hook) = &self.on_send {
+                hook.call(w);
+            }
             Ok(w)
         }
 
@@ -148,15 +1128,99 @@ mod channel {
     #[derive(Debug)]
     pub struct Receiver<T> {
         buffer: TransferBuffer,
+        on_recv: Option<Hook>,
         phantom_data: PhantomData<T>,
     }
 
-    impl<T: Copy> Receiver<T> {
+    impl<T: SharedSafe> Receiver<T> {
         pub fn new() -> io::Result<Self> {
+            Self::with_policy(WaitPolicy::default())
+        }
+
+        /// Creates a channel whose ends wait for each other according to `policy`. The
+        /// eventfds of `WaitPolicy::EventFd` are created here, so this has to happen before
+        /// forking just like for any other channel.
+        pub fn with_policy(wait_policy: WaitPolicy) -> io::Result<Self> {
+            Self::with_options(ChannelOptions {
+                wait_policy,
+                ..ChannelOptions::default()
+            })
+        }
+
+        /// Creates a channel whose buffer holds `buffer_size` bytes instead of just one `T`,
+        /// e.g. to also move larger chunks through it with `Read` and `Write`.
+        ///
+        /// Fails with `ErrorKind::InvalidInput` if a `T` doesn't fit into `buffer_size` bytes.
+        pub fn with_buffer_size(buffer_size: usize) -> io::Result<Self> {
+            if size_of::<T>() > buffer_size {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    ChannelError::BufferTooSmall {
+                        needed: size_of::<T>(),
+                        available: buffer_size,
+                    },
+                ));
+            }
+            let buffer = TransferBuffer::new(buffer_size, SENDER, ChannelOptions::default())?;
+            Ok(Receiver {
+                buffer,
+                on_recv: None,
+                phantom_data: PhantomData,
+            })
+        }
+
+        /// Creates a channel with a buffer of `buffer_size` bytes that grows whenever
+        /// `Sender::send_bytes` sends a larger payload, e.g. for a control channel that
+        /// usually carries small messages but has to take a large one once in a while. Like
+        /// for `channel`, the memory is shared with forked processes, so fork only after
+        /// creating it.
+        ///
+        /// Fails with `ErrorKind::InvalidInput` if a `T` doesn't fit into `buffer_size` bytes.
+        ///
+        /// Only available on Linux, as the shared memory behind it has to be resized.
+        #[cfg(target_os = "linux")]
+        pub fn growable(buffer_size: usize) -> io::Result<Self> {
+            if size_of::<T>() > buffer_size {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    ChannelError::BufferTooSmall {
+                        needed: size_of::<T>(),
+                        available: buffer_size,
+                    },
+                ));
+            }
+            let buffer = TransferBuffer::growable(buffer_size, SENDER, WaitPolicy::default())?;
+            Ok(Receiver {
+                buffer,
+                on_recv: None,
+                phantom_data: PhantomData,
+            })
+        }
+
+        /// Creates a channel with the given wait policy and buffer layout
+        pub fn with_options(options: ChannelOptions) -> io::Result<Self> {
             let buffer_size = size_of::<T>();
-            let buffer = TransferBuffer::new(buffer_size, SENDER)?;
+            let buffer = TransferBuffer::new(buffer_size, SENDER, options)?;
             Ok(Receiver {
                 buffer,
+                on_recv: None,
+                phantom_data: PhantomData,
+            })
+        }
+
+        /// Creates a channel backed by the file at `path`, so that a separately launched
+        /// process can send to it through `Sender::connect_named`. An existing file is
+        /// replaced, and the file isn't removed again when the receiver is dropped.
+        ///
+        /// Put the file on a tmpfs like `/dev/shm` to keep the messages in memory.
+        pub fn new_named<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+            Ok(Receiver {
+                buffer: TransferBuffer::create_named(
+                    path.as_ref(),
+                    MessageType::of::<T>(),
+                    SENDER,
+                )?,
+                on_recv: None,
                 phantom_data: PhantomData,
             })
         }
@@ -164,39 +1228,478 @@ mod channel {
         pub fn new_sender(&mut self) -> Sender<T> {
             let pointer = &mut self.buffer;
             Sender {
-                buffer: UnsafeCell::new(pointer),
+                buffer: UnsafeCell::new(SenderBuffer::Borrowed(pointer)),
+                on_send: None,
                 phantom_data: PhantomData,
             }
         }
 
         fn read_unaligned(&self) -> T {
+            debug_assert!(self.buffer.size() >= size_of::<T>());
             let ptr = self.buffer.buffer().as_ptr() as *const T;
             unsafe { ptr.read_unaligned() }
         }
     }
 
-    impl<T: Copy + Sized> Receiver<T> {
-        pub fn recv(&mut self) -> T {
+    impl<T: SharedSafe> Receiver<T> {
+        /// Receives the next message, blocking until there is one.
+        ///
+        /// With `ChannelOptions::checksum` a corrupted message fails with an
+        /// `ErrorKind::InvalidData` error. It's consumed all the same.
+        pub fn recv(&mut self) -> Result<T, ChannelError> {
+            self.recv_tagged().map(|(_tag, t)| t)
+        }
+
+        /// Like `recv`, but also returns the tag the message was sent with
+        pub fn recv_tagged(&mut self) -> Result<(u32, T), ChannelError> {
+            check_size::<T>(&self.buffer)?;
             self.buffer.wait_for_owner(RECEIVER);
+            if self.buffer.closed() {
+                return Err(ChannelError::Closed);
+            }
+            let checked = self.buffer.verify(size_of::<T>());
             let t = self.read_unaligned();
+            let tag = self.buffer.tag();
             self.buffer.write_owner(SENDER);
-            t
+            checked?;
+            self.received(size_of::<T>());
+            Ok((tag, t))
         }
-    }
 
-    impl<T> Read for Receiver<T> {
-        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-            self.buffer.wait_for_owner(RECEIVER);
-            let r = (&self.buffer.buffer()[..]).read(buf)?;
+        /// Like `recv`, but gives up after `timeout` and returns `Ok(None)`. A message that
+        /// wasn't received in time stays in the channel for the next call.
+        pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<T>, ChannelError> {
+            if !self.buffer.wait_for_owner_timeout(RECEIVER, timeout)? {
+                return Ok(None);
+            }
+            if self.buffer.closed() {
+                return Err(ChannelError::Closed);
+            }
+            let checked = self.buffer.verify(size_of::<T>());
+            let t = self.read_unaligned();
+            self.buffer.write_owner(SENDER);
+            checked?;
+            self.received(size_of::<T>());
+            Ok(Some(t))
+        }
+
+        /// Receives a message if one is ready, without ever blocking, and returns `Ok(None)`
+        /// otherwise. The owner byte is only touched if a message was actually taken out of
+        /// the channel. Like with `recv`, a message failing its checksum is consumed and
+        /// reported as an `ErrorKind::InvalidData` error. Once the sender closed the channel
+        /// this fails with `ChannelError::Closed`, so a polling loop knows when to stop.
+        ///
+        /// With `WaitPolicy::EventFd` this also resets the descriptor from `as_raw_fd`.
+        pub fn try_recv(&mut self) -> Result<Option<T>, ChannelError> {
+            self.buffer.clear_events(RECEIVER);
+            if self.buffer.current_owner() != RECEIVER {
+                return Ok(None);
+            }
+            if self.buffer.closed() {
+                return Err(ChannelError::Closed);
+            }
+            let checked = self.buffer.verify(size_of::<T>());
+            let t = self.read_unaligned();
+            self.buffer.write_owner(SENDER);
+            checked?;
+            self.received(size_of::<T>());
+            Ok(Some(t))
+        }
+
+        /// Iterates over the messages as they arrive, blocking in `next` until there is one.
+        ///
+        /// Ends once the sender closed the channel, or if receiving fails. Use `take` or
+        /// `iter_until` to stop earlier.
+        pub fn iter(&mut self) -> impl Iterator<Item = T> + '_ {
+            std::iter::from_fn(move || self.recv().ok())
+        }
+
+        /// Like `iter`, but ends once a message matches `stop`. That message is consumed but
+        /// not yielded, so a sender can mark the end of a stream with a sentinel value.
+        pub fn iter_until<'a>(
+            &'a mut self,
+            stop: impl Fn(&T) -> bool + 'a,
+        ) -> impl Iterator<Item = T> + 'a {
+            self.iter().take_while(move |t| !stop(t))
+        }
+
+        /// Like `recv`, but lends the message out of the shared memory instead of copying it,
+        /// e.g. to read a few fields of a large struct. The sender stays blocked until the
+        /// guard is dropped.
+        ///
+        /// Falls back to a copy if the payload isn't aligned for a `T`, which can only happen
+        /// with `Layout::OwnerFirst`.
+        pub fn recv_ref(&mut self) -> Result<RecvGuard<'_, T>, ChannelError> {
+            check_size::<T>(&self.buffer)?;
+            self.buffer.wait_for_owner(RECEIVER);
+            if self.buffer.closed() {
+                return Err(ChannelError::Closed);
+            }
+            if let Err(e) = self.buffer.verify(size_of::<T>()) {
+                self.buffer.write_owner(SENDER);
+                return Err(e.into());
+            }
+            let aligned = self.buffer.buffer().as_ptr().cast::<T>().is_aligned();
+            let copy = if aligned {
+                None
+            } else {
+                Some(self.read_unaligned())
+            };
+            Ok(RecvGuard {
+                receiver: self,
+                copy,
+            })
+        }
+    }
+
+    /// A message received with `Receiver::recv_ref`, derefs to the `T` in the channel. The
+    /// buffer is handed back to the sender when the guard is dropped.
+    #[derive(Debug)]
+    pub struct RecvGuard<'r, T> {
+        receiver: &'r mut Receiver<T>,
+        /// The message if the payload can't be referenced in place
+        copy: Option<T>,
+    }
+
+    impl<T> Deref for RecvGuard<'_, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            match &self.copy {
+                Some(t) => t,
+                // aligned and big enough as checked in `recv_ref`, and the sender can't touch
+                // the payload while the receiver owns it
+                None => unsafe { &*(self.receiver.buffer.buffer().as_ptr() as *const T) },
+            }
+        }
+    }
+
+    impl<T> Drop for RecvGuard<'_, T> {
+        fn drop(&mut self) {
+            self.receiver.buffer.write_owner(SENDER);
+            self.receiver.received(size_of::<T>());
+        }
+    }
+
+    /// Creates a connected pair of channel ends that, unlike `Receiver::new_sender`, don't
+    /// borrow each other. Both share their memory with the other end, so create the pair
+    /// before forking: the sending process keeps the `Sender` and the receiving process the
+    /// `Receiver`. Each process can simply drop the end it doesn't use.
+    pub fn channel<T: SharedSafe>() -> io::Result<(Sender<'static, T>, Receiver<T>)> {
+        let (sender_buffer, receiver_buffer) =
+            TransferBuffer::pair(size_of::<T>(), SENDER, ChannelOptions::default())?;
+        let sender = Sender {
+            buffer: UnsafeCell::new(SenderBuffer::Owned(sender_buffer)),
+            on_send: None,
+            phantom_data: PhantomData,
+        };
+        let receiver = Receiver {
+            buffer: receiver_buffer,
+            on_recv: None,
+            phantom_data: PhantomData,
+        };
+        Ok((sender, receiver))
+    }
+
+    /// One end of a channel that carries messages both ways, created by `duplex_channel`.
+    /// Each direction has its own buffer, so both ends can send without waiting for the
+    /// other one to receive first.
+    #[derive(Debug)]
+    pub struct Duplex<T> {
+        sender: Sender<'static, T>,
+        receiver: Receiver<T>,
+    }
+
+    impl<T: SharedSafe> Duplex<T> {
+        /// Sends `data` to the other end, blocking until it received the previous message
+        pub fn send(&mut self, data: T) -> Result<(), ChannelError> {
+            self.sender.send(data)
+        }
+
+        /// Receives the next message the other end sent
+        pub fn recv(&mut self) -> Result<T, ChannelError> {
+            self.receiver.recv()
+        }
+    }
+
+    /// Creates the two ends of a `Duplex` channel, what one sends the other receives. Like
+    /// `channel`, create them before forking and keep one end in each process.
+    pub fn duplex_channel<T: SharedSafe>() -> io::Result<(Duplex<T>, Duplex<T>)> {
+        let (a_sender, b_receiver) = channel()?;
+        let (b_sender, a_receiver) = channel()?;
+        Ok((
+            Duplex {
+                sender: a_sender,
+                receiver: a_receiver,
+            },
+            Duplex {
+                sender: b_sender,
+                receiver: b_receiver,
+            },
+        ))
+    }
+
+    /// Where the next `select` starts looking, so that one busy receiver can't starve the rest
+    static SELECT_START: AtomicUsize = AtomicUsize::new(0);
+
+    /// Waits until any of `receivers` has a message and receives it. Returns the index of
+    /// that receiver along with the message, or the error `try_recv` reported for it.
+    ///
+    /// Every call starts polling at a different receiver, so all of them get their turn even
+    /// if some are always ready.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `receivers` is empty.
+    pub fn select<T: SharedSafe>(
+        receivers: &mut [Receiver<T>],
+    ) -> (usize, Result<T, ChannelError>) {
+        assert!(!receivers.is_empty(), "select needs at least one receiver");
+        let n = receivers.len();
+        let start = SELECT_START.fetch_add(1, Ordering::Relaxed) % n;
+        loop {
+            for i in (start..n).chain(0..start) {
+                if let Some(result) = receivers[i].try_recv().transpose() {
+                    return (i, result);
+                }
+            }
+            spin_loop();
+        }
+    }
+
+    impl Receiver<()> {
+        /// Creates a channel for arrays of `N` elements of type `T`, so code that is generic
+        /// over the length can write `Receiver::array::<T, N>()` instead of spelling out the
+        /// array type. The buffer holds exactly `N * size_of::<T>()` bytes of payload.
+        pub fn array<T: SharedSafe, const N: usize>() -> io::Result<Receiver<[T; N]>> {
+            Receiver::<[T; N]>::new()
+        }
+    }
+
+    impl<T> Receiver<T> {
+        /// Receives the next message and appends its raw bytes to `file` by copying them from
+        /// the transfer buffer straight into a mapping of the file, without going through the
+        /// heap. Returns the number of bytes written, or 0 once the sender closed the channel.
+        ///
+        /// If the file can't be extended or mapped the message stays in the channel.
+        pub fn recv_to_file(&mut self, file: &mut File) -> io::Result<usize> {
+            self.buffer.wait_for_owner(RECEIVER);
+            if self.buffer.closed() {
+                return Ok(0);
+            }
+            let payload = self.buffer.buffer();
+            if !payload.is_empty() {
+                let offset = file.metadata()?.len();
+                file.set_len(offset + payload.len() as u64)?;
+                let mut destination = unsafe {
+                    MmapOptions::new()
+                        .offset(offset)
+                        .len(payload.len())
+                        .map_mut(&*file)?
+                };
+                destination.copy_from_slice(payload);
+                destination.flush()?;
+            }
+            let written = payload.len();
+            self.buffer.write_owner(SENDER);
+            Ok(written)
+        }
+    }
+
+    impl<T> Receiver<T> {
+        /// Fills `out` completely, receiving as many frames as needed - the byte channel
+        /// analog of `Read::read_exact`. Pairs with `write_all` on the sending side.
+        ///
+        /// Every frame is consumed as a whole, so both sides have to agree on the total length.
+        /// Fails with `ErrorKind::UnexpectedEof` if the sender closed the channel before
+        /// enough bytes arrived to fill `out`.
+        pub fn recv_exact_bytes(&mut self, out: &mut [u8]) -> io::Result<()> {
+            self.read_exact(out)
+        }
+
+        /// Receives a payload sent with `Sender::send_bytes`, replacing the contents of `out`.
+        /// Returns the length of the payload.
+        pub fn recv_bytes(&mut self, out: &mut Vec<u8>) -> Result<usize, ChannelError> {
+            check_size::<u64>(&self.buffer)?;
+            self.buffer.wait_for_owner(RECEIVER);
+            if self.buffer.closed() {
+                return Err(ChannelError::Closed);
+            }
+            let mut length = [0; size_of::<u64>()];
+            length.copy_from_slice(&self.buffer.buffer()[..size_of::<u64>()]);
+            let length = u64::from_ne_bytes(length) as usize;
+            // the sender already grew the shared memory if the payload needs more room
+            self.buffer.grow(length)?;
             self.buffer.write_owner(SENDER);
+
+            out.clear();
+            out.resize(length, 0);
+            for chunk in out.chunks_mut(self.buffer.size()) {
+                self.buffer.wait_for_owner(RECEIVER);
+                chunk.copy_from_slice(&self.buffer.buffer()[..chunk.len()]);
+                self.buffer.write_owner(SENDER);
+            }
+            Ok(length)
+        }
+
+        /// Like `recv_bytes`, but gives up once `timeout` passed. Returns `Ok(None)` if not
+        /// even the length of a payload arrived in time, which leaves the channel untouched.
+        ///
+        /// A timeout in the middle of the payload fails with `ChannelError::Partial`, holding
+        /// the chunks received so far. Pass it to `resume_large_timeout` to receive the rest
+        /// instead of starting over.
+        pub fn recv_large_timeout(
+            &mut self,
+            timeout: Duration,
+        ) -> Result<Option<Vec<u8>>, ChannelError> {
+            let deadline = Instant::now() + timeout;
+            check_size::<u64>(&self.buffer)?;
+            if !self.buffer.wait_for_owner_timeout(RECEIVER, timeout)? {
+                return Ok(None);
+            }
+            if self.buffer.closed() {
+                return Err(ChannelError::Closed);
+            }
+            let mut length = [0; size_of::<u64>()];
+            length.copy_from_slice(&self.buffer.buffer()[..size_of::<u64>()]);
+            let length = u64::from_ne_bytes(length) as usize;
+            self.buffer.grow(length)?;
+            self.buffer.write_owner(SENDER);
+
+            let partial = PartialTransfer {
+                received: Vec::with_capacity(length),
+                total: length,
+                chunks: 0,
+            };
+            self.recv_chunks_until(partial, deadline).map(Some)
+        }
+
+        /// Receives the rest of a payload `recv_large_timeout` gave up on, again waiting at
+        /// most `timeout` before failing with `ChannelError::Partial`.
+        pub fn resume_large_timeout(
+            &mut self,
+            partial: PartialTransfer,
+            timeout: Duration,
+        ) -> Result<Vec<u8>, ChannelError> {
+            self.recv_chunks_until(partial, Instant::now() + timeout)
+        }
+
+        fn recv_chunks_until(
+            &mut self,
+            mut partial: PartialTransfer,
+            deadline: Instant,
+        ) -> Result<Vec<u8>, ChannelError> {
+            while partial.received.len() < partial.total {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if !self.buffer.wait_for_owner_timeout(RECEIVER, remaining)? {
+                    return Err(ChannelError::Partial(partial));
+                }
+                let len = (partial.total - partial.received.len()).min(self.buffer.size());
+                partial
+                    .received
+                    .extend_from_slice(&self.buffer.buffer()[..len]);
+                self.buffer.write_owner(SENDER);
+                partial.chunks += 1;
+            }
+            Ok(partial.received)
+        }
+    }
+
+    impl<T> Receiver<T> {
+        /// Installs a callback that is called after every message this end received, with
+        /// the size of the message and the time it was handed back. `Read` calls it once per
+        /// frame, `recv_ref` once the guard is dropped. Without a hook receiving costs nothing
+        /// extra.
+        pub fn set_on_recv(&mut self, hook: impl Fn(usize, Instant) + Send + 'static) {
+            self.on_recv = Some(Hook(Box::new(hook)));
+        }
+
+        /// The eventfd of a channel created with `WaitPolicy::EventFd`, or `None` for any other
+        /// policy. It becomes readable when a message arrives, so it can be registered with
+        /// `epoll` or an async reactor: once it's readable, call `try_recv` until it returns
+        /// `None`. Don't read from the descriptor directly, `try_recv` resets it.
+        pub fn as_raw_fd(&self) -> Option<RawFd> {
+            match self.buffer.waker {
+                Waker::EventFd(events) => Some(events[RECEIVER as usize]),
+                _ => None,
+            }
+        }
+
+        fn received(&self, size: usize) {
+            if let Some(hook) = &self.on_recv {
+                hook.call(size);
+            }
+        }
+    }
+
+    impl<T> Read for Receiver<T> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.buffer.wait_for_owner(RECEIVER);
+            if self.buffer.closed() {
+                return Ok(0);
+            }
+            let r = (&self.buffer.buffer()[..]).read(buf)?;
+            self.buffer.write_owner(SENDER);
+            self.received(r);
             Ok(r)
         }
     }
 
+    /// One direction of the connection between two ranks of a process group. It's created
+    /// before forking, afterwards one rank only sends on its copy and the other only receives.
+    #[derive(Debug)]
+    pub struct Link {
+        buffer: TransferBuffer,
+    }
+
+    impl Link {
+        /// Payload per frame, chosen so that a link takes up exactly one page
+        const FRAME: usize = 4096 - size_of::<Control>();
+
+        pub(crate) fn new() -> io::Result<Self> {
+            let buffer = TransferBuffer::new(Self::FRAME, SENDER, ChannelOptions::default())?;
+            Ok(Link { buffer })
+        }
+
+        /// Creates the receiving end of a link backed by the file at `path`, for ranks that
+        /// were forked already. The sending rank attaches with `open_named`.
+        pub(crate) fn create_named(path: &Path) -> io::Result<Self> {
+            let ty = MessageType::of::<[u8; Self::FRAME]>();
+            let buffer = TransferBuffer::create_named(path, ty, SENDER)?;
+            Ok(Link { buffer })
+        }
+
+        pub(crate) fn open_named(path: &Path) -> io::Result<Self> {
+            let ty = MessageType::of::<[u8; Self::FRAME]>();
+            let buffer = TransferBuffer::open_named(path, ty)?;
+            Ok(Link { buffer })
+        }
+
+        /// Sends `data` in as many frames as needed. The receiver has to ask for exactly
+        /// `data.len()` bytes.
+        pub(crate) fn send_bytes(&mut self, data: &[u8]) {
+            for chunk in data.chunks(Self::FRAME) {
+                self.buffer.wait_for_owner(SENDER);
+                self.buffer.buffer_mut()[..chunk.len()].copy_from_slice(chunk);
+                self.buffer.write_owner(RECEIVER);
+            }
+        }
+
+        pub(crate) fn recv_bytes(&mut self, out: &mut [u8]) {
+            for chunk in out.chunks_mut(Self::FRAME) {
+                self.buffer.wait_for_owner(RECEIVER);
+                chunk.copy_from_slice(&self.buffer.buffer()[..chunk.len()]);
+                self.buffer.write_owner(SENDER);
+            }
+        }
+    }
+
     #[cfg(test)]
     pub mod tests {
         use super::*;
 
+        use nix::sys::wait::waitpid;
+
         #[derive(Debug, Copy, Clone, PartialEq, Default)]
         struct Test {
             a: usize,
@@ -208,6 +1711,7 @@ mod channel {
                 Test { a, b, c }
             }
         }
+        unsafe impl SharedSafe for Test {}
 
         #[test]
         pub fn simple_transfer() {
@@ -226,86 +1730,987 @@ mod channel {
 
             match fork() {
                 Ok(ForkResult::Parent { child, .. }) => {
-                    sender1.send(123);
-                    sender1.send(456);
-                    sender2.send(data2);
-                    assert_eq!(receiver3.recv(), data3);
+                    sender1.send(123).unwrap();
+                    sender1.send(456).unwrap();
+                    sender2.send(data2).unwrap();
+                    assert_eq!(receiver3.recv().unwrap(), data3);
                     wait_for_process::<fn(&Process)>(child, None);
                 }
                 Ok(ForkResult::Child) => {
-                    assert_eq!(receiver1.recv(), 123);
-                    assert_eq!(receiver1.recv(), 456);
-                    assert_eq!(receiver2.recv(), data2);
-                    sender3.send(data3);
+                    assert_eq!(receiver1.recv().unwrap(), 123);
+                    assert_eq!(receiver1.recv().unwrap(), 456);
+                    assert_eq!(receiver2.recv().unwrap(), data2);
+                    sender3.send(data3).unwrap();
                 }
                 Err(e) => panic!("fork failed: {}", e),
             }
         }
-    }
 
-    pub fn bench_data_rate() {
-        const BUFFER_SIZE: usize = 1024 * 1024; // set back to 32 if you want to compare to servo
-        const IMAX: usize = 100_000;
-        const LENGTHS: usize = 3;
+        #[test]
+        pub fn recv_into_file() {
+            let mut receiver = Receiver::<[u8; 100]>::new().unwrap();
+            let mut sender = receiver.new_sender();
+            let mut data1 = [0; 100];
+            let mut data2 = [0; 100];
+            for i in 0..100 {
+                data1[i] = i as u8;
+                data2[i] = 255 - i as u8;
+            }
 
-        let mut receiver = Receiver::<[u8; BUFFER_SIZE]>::new().unwrap();
-        let mut sender = receiver.new_sender();
-        match fork() {
-            Ok(ForkResult::Parent { child, .. }) => {
-                let mut times = Vec::new();
-                let pid = std::process::id();
-                println!("Receiver: {}, Sender: {}", pid, child);
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    let path = std::env::temp_dir()
+                        .join(format!("mpi2-recv-into-file-{}", std::process::id()));
+                    let mut file = std::fs::OpenOptions::new()
+                        .read(true)
+                        .write(true)
+                        .create(true)
+                        .truncate(true)
+                        .open(&path)
+                        .unwrap();
+                    assert_eq!(receiver.recv_to_file(&mut file).unwrap(), 100);
+                    assert_eq!(receiver.recv_to_file(&mut file).unwrap(), 100);
+                    let contents = std::fs::read(&path).unwrap();
+                    std::fs::remove_file(&path).unwrap();
+                    assert_eq!(&contents[..100], &data1[..]);
+                    assert_eq!(&contents[100..], &data2[..]);
+                    waitpid(child, None).unwrap();
+                }
+                Ok(ForkResult::Child) => {
+                    sender.send(data1).unwrap();
+                    sender.send(data2).unwrap();
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
 
-                for _ in 0..LENGTHS {
+        #[test]
+        pub fn exact_bytes_span_frames() {
+            let mut receiver = Receiver::<[u8; 16]>::new().unwrap();
+            let mut sender = receiver.new_sender();
+            let data: Vec<u8> = (0..40).collect();
+
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    let mut out = [0; 40];
+                    receiver.recv_exact_bytes(&mut out).unwrap();
+                    assert_eq!(&out[..], &data[..]);
+                    waitpid(child, None).unwrap();
+                }
+                Ok(ForkResult::Child) => {
+                    sender.write_all(&data).unwrap();
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
+
+        #[test]
+        pub fn large_recv_resumes_after_timeout() {
+            const BUFFER_SIZE: usize = 64;
+            const CHUNKS: usize = 10;
+            let payload: Vec<u8> = (0..CHUNKS * BUFFER_SIZE).map(|i| i as u8).collect();
+
+            let mut receiver = Receiver::<[u8; BUFFER_SIZE]>::new().unwrap();
+            let mut sender = receiver.new_sender();
+            let (mut go_tx, mut go_rx) = channel::<u8>().unwrap();
+
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    assert_eq!(
+                        receiver
+                            .recv_large_timeout(Duration::from_millis(1))
+                            .unwrap(),
+                        None
+                    );
+                    let partial = match receiver.recv_large_timeout(Duration::from_millis(500)) {
+                        Err(ChannelError::Partial(partial)) => partial,
+                        other => panic!("expected a partial transfer, got {:?}", other),
+                    };
+                    assert_eq!(partial.chunks, 4);
+                    assert_eq!(partial.total, payload.len());
+                    assert_eq!(partial.received, &payload[..4 * BUFFER_SIZE]);
+
+                    go_tx.send(1).unwrap();
+                    let out = receiver
+                        .resume_large_timeout(partial, Duration::from_secs(10))
+                        .unwrap();
+                    assert_eq!(out, payload);
+                    waitpid(child, None).unwrap();
+                }
+                Ok(ForkResult::Child) => {
+                    std::thread::sleep(Duration::from_millis(50));
+                    // `send_bytes` by hand, to stall after four chunks
+                    let buf = sender.get_buffer_mut().unwrap();
+                    buf.wait_for_owner(SENDER);
+                    buf.buffer_mut()[..size_of::<u64>()]
+                        .copy_from_slice(&(payload.len() as u64).to_ne_bytes());
+                    buf.write_owner(RECEIVER);
+                    for (i, chunk) in payload.chunks(BUFFER_SIZE).enumerate() {
+                        if i == 4 {
+                            go_rx.recv().unwrap();
+                        }
+                        buf.wait_for_owner(SENDER);
+                        buf.buffer_mut()[..chunk.len()].copy_from_slice(chunk);
+                        buf.write_owner(RECEIVER);
+                    }
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
+
+        #[test]
+        pub fn chunked_bytes() {
+            const BUFFER_SIZE: usize = 64;
+            let lengths = [0, 1, BUFFER_SIZE, 3 * BUFFER_SIZE + 7];
+            let payload = |length: usize| (0..length).map(|i| i as u8).collect::<Vec<_>>();
+
+            let mut receiver = Receiver::<[u8; BUFFER_SIZE]>::new().unwrap();
+            let mut sender = receiver.new_sender();
+
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    // stale contents have to be replaced
+                    let mut out = vec![0xff; 10];
+                    for &length in lengths.iter() {
+                        assert_eq!(receiver.recv_bytes(&mut out).unwrap(), length);
+                        assert_eq!(out, payload(length));
+                    }
+                    waitpid(child, None).unwrap();
+                }
+                Ok(ForkResult::Child) => {
+                    for &length in lengths.iter() {
+                        sender.send_bytes(&payload(length)).unwrap();
+                    }
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
+
+        #[test]
+        #[cfg(target_os = "linux")]
+        pub fn growable_buffer() {
+            const BUFFER_SIZE: usize = 64;
+            let lengths = [16, 4 * BUFFER_SIZE, 16];
+            let payload = |length: usize| (0..length).map(|i| i as u8).collect::<Vec<_>>();
+
+            let mut receiver = Receiver::<u8>::growable(BUFFER_SIZE).unwrap();
+            let mut sender = receiver.new_sender();
+
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    let mut out = Vec::new();
+                    for &length in lengths.iter() {
+                        assert_eq!(receiver.recv_bytes(&mut out).unwrap(), length);
+                        assert_eq!(out, payload(length));
+                    }
+                    assert_eq!(receiver.buffer.size(), 4 * BUFFER_SIZE);
+                    waitpid(child, None).unwrap();
+                }
+                Ok(ForkResult::Child) => {
+                    for &length in lengths.iter() {
+                        sender.send_bytes(&payload(length)).unwrap();
+                    }
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
+
+        fn recv_timeout_with(wait_policy: WaitPolicy) {
+            let mut receiver = Receiver::<u32>::with_policy(wait_policy).unwrap();
+            let mut sender = receiver.new_sender();
+
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    let timeout = Duration::from_millis(50);
                     let t1 = Instant::now();
-                    for _ in 0..IMAX {
-                        let _dat = receiver.recv();
+                    assert_eq!(receiver.recv_timeout(timeout).unwrap(), None);
+                    assert!(t1.elapsed() >= timeout);
+                    assert_eq!(
+                        receiver.recv_timeout(Duration::from_secs(10)).unwrap(),
+                        Some(1234)
+                    );
+                    waitpid(child, None).unwrap();
+                }
+                Ok(ForkResult::Child) => {
+                    std::thread::sleep(Duration::from_millis(300));
+                    sender.send(1234).unwrap();
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
+
+        #[test]
+        pub fn recv_timeout() {
+            recv_timeout_with(WaitPolicy::Spin);
+            recv_timeout_with(WaitPolicy::Futex);
+            #[cfg(target_os = "linux")]
+            recv_timeout_with(WaitPolicy::EventFd);
+            recv_timeout_with(WaitPolicy::SpinThenYield(Backoff::default()));
+            // straight to napping
+            recv_timeout_with(WaitPolicy::SpinThenYield(Backoff {
+                spins: 0,
+                yields: 0,
+            }));
+        }
+
+        #[test]
+        pub fn close_ends_stream() {
+            const N: u64 = 5;
+            let (mut sender, mut receiver) = channel::<u64>().unwrap();
+
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    assert_eq!(
+                        receiver.iter().collect::<Vec<_>>(),
+                        (0..N).collect::<Vec<_>>()
+                    );
+                    // stays closed
+                    assert!(matches!(receiver.recv(), Err(ChannelError::Closed)));
+                    assert!(matches!(receiver.try_recv(), Err(ChannelError::Closed)));
+                    assert!(matches!(
+                        receiver.recv_timeout(Duration::from_secs(1)),
+                        Err(ChannelError::Closed)
+                    ));
+                    assert!(matches!(receiver.irecv().test(), Err(ChannelError::Closed)));
+                    assert_eq!(receiver.read(&mut [0; 8]).unwrap(), 0);
+                    waitpid(child, None).unwrap();
+                }
+                Ok(ForkResult::Child) => {
+                    for i in 0..N {
+                        sender.send(i).unwrap();
                     }
-                    let t2 = Instant::now() - t1;
-                    times.push((BUFFER_SIZE, t2));
+                    sender.close().unwrap();
+                    std::process::exit(0);
                 }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
 
-                for (message_length, t2) in times {
-                    println!(
-                        "Rx, pid: {:?}, length: {:-6}, time: {:?}, latency: {:?}, bandwith: {:e}byte/s",
-                        pid,
-                        message_length,
-                        t2,
-                        t2.checked_div(IMAX as u32).unwrap(),
-                        10.0f64.powf(9.0) * (message_length * IMAX) as f64 / t2.as_nanos() as f64
+        #[test]
+        pub fn try_send_fails_fast() {
+            let (mut sender, mut receiver) = channel::<(u32, f64)>().unwrap();
+            assert_eq!(sender.try_send((1, 0.5)), Ok(()));
+            // the slot is still taken
+            assert_eq!(sender.try_send((2, 1.5)), Err((2, 1.5)));
+            assert_eq!(receiver.recv().unwrap(), (1, 0.5));
+            assert_eq!(sender.try_send((3, 2.5)), Ok(()));
+            assert_eq!(receiver.recv().unwrap(), (3, 2.5));
+        }
+
+        #[test]
+        pub fn send_timeout_returns_value() {
+            let (mut sender, mut receiver) = channel::<[u32; 4]>().unwrap();
+
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    assert_eq!(
+                        sender.send_timeout([1; 4], Duration::from_millis(100)),
+                        Ok(())
                     );
+                    // the child never receives the first message
+                    let start = Instant::now();
+                    let result = sender.send_timeout([2; 4], Duration::from_millis(100));
+                    assert_eq!(result, Err([2; 4]));
+                    assert!(start.elapsed() >= Duration::from_millis(100));
+                    // the first message wasn't overwritten
+                    assert_eq!(receiver.try_recv().unwrap(), Some([1; 4]));
+                    waitpid(child, None).unwrap();
                 }
-                wait_for_process(child, Some((Duration::from_secs(10), &kill_process)));
-                println!("Parent shutting down");
+                Ok(ForkResult::Child) => {
+                    std::thread::sleep(Duration::from_millis(300));
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
             }
-            Ok(ForkResult::Child) => {
-                // sender
-                let mut times = Vec::new();
-                let pid = std::process::id();
-                let buf = [0; BUFFER_SIZE];
+        }
 
-                for _ in 0..LENGTHS {
+        #[test]
+        pub fn select_returns_ready_receivers() {
+            let (mut first_tx, first_rx) = channel::<u32>().unwrap();
+            let (mut second_tx, second_rx) = channel::<u32>().unwrap();
+            let mut receivers = vec![first_rx, second_rx];
+
+            let mut children = Vec::new();
+            for (index, sender) in [&mut first_tx, &mut second_tx].iter_mut().enumerate() {
+                match fork() {
+                    Ok(ForkResult::Parent { child, .. }) => children.push(child),
+                    Ok(ForkResult::Child) => {
+                        sender.send(index as u32 * 10).unwrap();
+                        std::process::exit(0);
+                    }
+                    Err(e) => panic!("fork failed: {}", e),
+                }
+            }
+
+            let mut received = (0..2)
+                .map(|_| {
+                    let (index, result) = select(&mut receivers);
+                    (index, result.unwrap())
+                })
+                .collect::<Vec<_>>();
+            received.sort();
+            assert_eq!(received, vec![(0, 0), (1, 10)]);
+            for child in children {
+                waitpid(child, None).unwrap();
+            }
+        }
+
+        #[test]
+        pub fn select_is_fair() {
+            let (mut first_tx, first_rx) = channel::<u32>().unwrap();
+            let (mut second_tx, second_rx) = channel::<u32>().unwrap();
+            let mut receivers = vec![first_rx, second_rx];
+
+            // both are ready all the time, so the rotation alone decides who is picked
+            let mut picked = [0; 2];
+            for _ in 0..10 {
+                first_tx.send(0).unwrap();
+                second_tx.send(1).unwrap();
+                let (index, _) = select(&mut receivers);
+                picked[index] += 1;
+                let other = 1 - index;
+                assert_eq!(receivers[other].recv().unwrap(), other as u32);
+            }
+            assert!(picked[0] > 0 && picked[1] > 0, "picked {:?}", picked);
+        }
+
+        #[test]
+        pub fn no_torn_messages() {
+            const COUNT: u64 = 20_000;
+            // every word of a message holds the same value, a torn one mixes two of them
+            let mut receiver = Receiver::<[u64; 16]>::new().unwrap();
+            let mut sender = receiver.new_sender();
+
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    for i in 0..COUNT {
+                        assert_eq!(receiver.recv().unwrap(), [i; 16]);
+                    }
+                    waitpid(child, None).unwrap();
+                }
+                Ok(ForkResult::Child) => {
+                    for i in 0..COUNT {
+                        sender.send([i; 16]).unwrap();
+                    }
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
+
+        #[test]
+        pub fn named_channel_round_trip() {
+            let path =
+                std::env::temp_dir().join(format!("mpi2-named-channel-{}", std::process::id()));
+            let mut receiver = Receiver::<(u64, f64)>::new_named(&path).unwrap();
+
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    assert_eq!(receiver.recv().unwrap(), (1, 0.5));
+                    assert_eq!(receiver.recv().unwrap(), (2, 0.25));
+                    waitpid(child, None).unwrap();
+                    std::fs::remove_file(&path).unwrap();
+                }
+                Ok(ForkResult::Child) => {
+                    // attach by path only, like an independently launched program would
+                    let mut sender = Sender::connect_named(&path).unwrap();
+                    sender.send((1u64, 0.5f64)).unwrap();
+                    sender.send((2, 0.25)).unwrap();
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
+
+        #[test]
+        pub fn connecting_keeps_pending_message() {
+            let path =
+                std::env::temp_dir().join(format!("mpi2-named-pending-{}", std::process::id()));
+            let mut receiver = Receiver::<u32>::new_named(&path).unwrap();
+
+            Sender::<u32>::connect_named(&path)
+                .unwrap()
+                .send(7)
+                .unwrap();
+            let mut late_sender = Sender::<u32>::connect_named(&path).unwrap();
+            assert_eq!(receiver.try_recv().unwrap(), Some(7));
+            late_sender.send(8).unwrap();
+            assert_eq!(receiver.recv().unwrap(), 8);
+
+            let err = Sender::<u64>::connect_named(&path).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+            // same size, but not the same type
+            let err = Sender::<f32>::connect_named(&path).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+            let err = Sender::<[u8; 4]>::connect_named(&path).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+            std::fs::remove_file(&path).unwrap();
+            let err = Sender::<u32>::connect_named(&path).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::NotFound);
+        }
+
+        #[test]
+        pub fn buffer_size_must_fit_type() {
+            let err = Receiver::<[u64; 4]>::with_buffer_size(16).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+            assert_eq!(
+                err.to_string(),
+                "message of 32 bytes doesn't fit into buffer of 16 bytes"
+            );
+
+            let mut receiver = Receiver::<[u64; 4]>::with_buffer_size(64).unwrap();
+            receiver.new_sender().send([1, 2, 3, 4]).unwrap();
+            assert_eq!(receiver.recv().unwrap(), [1, 2, 3, 4]);
+        }
+
+        #[test]
+        pub fn undersized_buffer_is_an_error() {
+            // a sender whose buffer can't hold its message type
+            let buffer = TransferBuffer::new(0, SENDER, ChannelOptions::default()).unwrap();
+            let mut sender = Sender::<u64> {
+                buffer: UnsafeCell::new(SenderBuffer::Owned(buffer)),
+                on_send: None,
+                phantom_data: PhantomData,
+            };
+            match sender.send(1) {
+                Err(ChannelError::BufferTooSmall { needed, available }) => {
+                    assert_eq!((needed, available), (8, 0));
+                }
+                other => panic!("expected BufferTooSmall, got {:?}", other),
+            }
+            // nothing was handed over
+            assert_eq!(sender.get_buffer_ref().unwrap().current_owner(), SENDER);
+        }
+
+        #[test]
+        pub fn tags_arrive_in_order() {
+            let mut receiver = Receiver::<u64>::new().unwrap();
+            let mut sender = receiver.new_sender();
+
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    assert_eq!(receiver.recv_tagged().unwrap(), (7, 70));
+                    assert_eq!(receiver.recv_tagged().unwrap(), (8, 80));
+                    assert_eq!(receiver.recv_tagged().unwrap(), (9, 90));
+                    assert_eq!(receiver.recv_tagged().unwrap(), (0, 100));
+                    waitpid(child, None).unwrap();
+                }
+                Ok(ForkResult::Child) => {
+                    sender.send_tagged(7, 70).unwrap();
+                    sender.send_tagged(8, 80).unwrap();
+                    sender.send_tagged(9, 90).unwrap();
+                    sender.send(100).unwrap();
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
+
+        #[test]
+        pub fn iter_until_sentinel() {
+            let mut receiver = Receiver::<u32>::new().unwrap();
+            let mut sender = receiver.new_sender();
+
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    let received: Vec<_> = receiver.iter_until(|&x| x == 0).collect();
+                    assert_eq!(received, vec![5, 4, 3, 2, 1]);
+                    // the sentinel was consumed, the next message is the one after it
+                    assert_eq!(receiver.iter().take(1).collect::<Vec<_>>(), vec![9]);
+                    waitpid(child, None).unwrap();
+                }
+                Ok(ForkResult::Child) => {
+                    for x in [5, 4, 3, 2, 1, 0, 9].iter() {
+                        sender.send(*x).unwrap();
+                    }
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
+
+        #[test]
+        pub fn try_recv_polls() {
+            let mut receiver = Receiver::<u32>::new().unwrap();
+            let mut sender = receiver.new_sender();
+
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    let mut misses = 0;
+                    let value = loop {
+                        match receiver.try_recv().unwrap() {
+                            Some(value) => break value,
+                            None => misses += 1,
+                        }
+                        std::thread::sleep(Duration::from_millis(1));
+                    };
+                    assert_eq!(value, 5);
+                    assert!(misses > 0);
+                    // none of the failed attempts may have released the buffer to the sender
+                    assert_eq!(receiver.recv().unwrap(), 6);
+                    waitpid(child, None).unwrap();
+                }
+                Ok(ForkResult::Child) => {
+                    std::thread::sleep(Duration::from_millis(100));
+                    sender.send(5).unwrap();
+                    sender.send(6).unwrap();
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
+
+        #[test]
+        pub fn hooks_fire_per_message() {
+            use std::sync::Arc;
+
+            let sent = Arc::new(AtomicUsize::new(0));
+            let received = Arc::new(AtomicUsize::new(0));
+            let (mut sender, mut receiver) = channel::<[u16; 3]>().unwrap();
+            let counter = sent.clone();
+            sender.set_on_send(move |size, _| {
+                assert_eq!(size, 6);
+                counter.fetch_add(1, Ordering::SeqCst);
+            });
+            let counter = received.clone();
+            receiver.set_on_recv(move |size, _| {
+                counter.fetch_add(size, Ordering::SeqCst);
+            });
+
+            for i in 0..5 {
+                sender.send([i; 3]).unwrap();
+                assert_eq!(sent.load(Ordering::SeqCst), i as usize + 1);
+                assert_eq!(received.load(Ordering::SeqCst), 6 * i as usize);
+                receiver.recv().unwrap();
+            }
+            assert_eq!(received.load(Ordering::SeqCst), 30);
+        }
+
+        #[test]
+        pub fn channel_round_trip() {
+            let (mut request_tx, mut request_rx) = channel::<u64>().unwrap();
+            let (mut reply_tx, mut reply_rx) = channel::<u64>().unwrap();
+
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    request_tx.send(21).unwrap();
+                    assert_eq!(reply_rx.recv().unwrap(), 42);
+                    waitpid(child, None).unwrap();
+                }
+                Ok(ForkResult::Child) => {
+                    let request = request_rx.recv().unwrap();
+                    reply_tx.send(request * 2).unwrap();
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
+
+        #[test]
+        pub fn duplex_echo() {
+            let (mut parent, mut child_end) = duplex_channel::<u64>().unwrap();
+
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    for x in 0..100 {
+                        parent.send(x).unwrap();
+                        assert_eq!(parent.recv().unwrap(), x + 1);
+                    }
+                    waitpid(child, None).unwrap();
+                }
+                Ok(ForkResult::Child) => {
+                    for _ in 0..100 {
+                        let x = child_end.recv().unwrap();
+                        child_end.send(x + 1).unwrap();
+                    }
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
+
+        #[test]
+        pub fn owner_byte_layouts() {
+            let data = [7u8; 32];
+            for &layout in [Layout::OwnerLast, Layout::OwnerFirst].iter() {
+                let mut receiver = Receiver::<[u8; 32]>::with_options(ChannelOptions {
+                    layout,
+                    ..ChannelOptions::default()
+                })
+                .unwrap();
+                receiver.new_sender().send(data).unwrap();
+
+                let offset = receiver.buffer.control_offset();
+                match layout {
+                    Layout::OwnerLast => assert!(offset >= data.len()),
+                    Layout::OwnerFirst => assert_eq!(offset, 0),
+                }
+                let owner = |mmap: &MmapMut| {
+                    let mut word = [0; 4];
+                    word.copy_from_slice(&mmap[offset..offset + 4]);
+                    u32::from_ne_bytes(word)
+                };
+                assert_eq!(owner(&receiver.buffer.mmap), RECEIVER as u32);
+                assert_eq!(receiver.recv().unwrap(), data);
+                assert_eq!(owner(&receiver.buffer.mmap), SENDER as u32);
+            }
+        }
+
+        #[test]
+        pub fn checksum_catches_corruption() {
+            // the standard check value
+            assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+
+            let mut receiver = Receiver::<[u64; 4]>::with_options(ChannelOptions {
+                checksum: true,
+                ..ChannelOptions::default()
+            })
+            .unwrap();
+            receiver.new_sender().send([1, 2, 3, 4]).unwrap();
+            receiver.buffer.buffer_mut()[13] ^= 0x10;
+            match receiver.recv() {
+                Err(ChannelError::Io(e)) => assert_eq!(e.kind(), ErrorKind::InvalidData),
+                other => panic!("corruption went unnoticed: {:?}", other),
+            }
+
+            // the broken message is gone, the next one arrives intact
+            receiver.new_sender().send([5, 6, 7, 8]).unwrap();
+            assert_eq!(receiver.recv().unwrap(), [5, 6, 7, 8]);
+
+            // polling reports it just the same instead of dropping it
+            receiver.new_sender().send([1, 2, 3, 4]).unwrap();
+            receiver.buffer.buffer_mut()[0] ^= 0x01;
+            match receiver.try_recv() {
+                Err(ChannelError::Io(e)) => assert_eq!(e.kind(), ErrorKind::InvalidData),
+                other => panic!("corruption went unnoticed: {:?}", other),
+            }
+            assert_eq!(receiver.try_recv().unwrap(), None);
+        }
+
+        #[test]
+        pub fn select_reports_corruption() {
+            let options = ChannelOptions {
+                checksum: true,
+                ..ChannelOptions::default()
+            };
+            let mut receivers = vec![
+                Receiver::<u64>::with_options(options).unwrap(),
+                Receiver::<u64>::with_options(options).unwrap(),
+            ];
+            receivers[1].new_sender().send(9).unwrap();
+            receivers[1].buffer.buffer_mut()[2] ^= 0x80;
+            match select(&mut receivers) {
+                (1, Err(ChannelError::Io(e))) => assert_eq!(e.kind(), ErrorKind::InvalidData),
+                other => panic!("corruption went unnoticed: {:?}", other),
+            }
+            receivers[0].new_sender().send(3).unwrap();
+            let (index, result) = select(&mut receivers);
+            assert_eq!((index, result.unwrap()), (0, 3));
+        }
+
+        #[test]
+        pub fn full_payload_keeps_control() {
+            for &layout in [Layout::OwnerLast, Layout::OwnerFirst].iter() {
+                let options = ChannelOptions {
+                    layout,
+                    ..ChannelOptions::default()
+                };
+                // odd sized, so the control word is padded in the OwnerLast layout
+                let mut buffer = TransferBuffer::new(13, RECEIVER, options).unwrap();
+                buffer.set_tag(5);
+                assert_eq!(buffer.buffer_mut().len(), 13);
+                for byte in buffer.buffer_mut() {
+                    *byte = 0xff;
+                }
+                assert_eq!(buffer.current_owner(), RECEIVER);
+                assert_eq!(buffer.tag(), 5);
+                assert_eq!(buffer.control().waiters.load(Ordering::SeqCst), 0);
+            }
+        }
+
+        #[derive(Clone, Copy)]
+        struct Large {
+            id: u64,
+            data: [u64; 1 << 14],
+        }
+        unsafe impl SharedSafe for Large {}
+
+        #[test]
+        pub fn recv_ref_borrows_in_place() {
+            let mut receiver = Receiver::<Large>::new().unwrap();
+            let payload = receiver.buffer.buffer().as_ptr();
+            let mut sender = receiver.new_sender();
+
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    for id in 0..3 {
+                        let large = receiver.recv_ref().unwrap();
+                        assert_eq!(&*large as *const Large as *const u8, payload);
+                        assert_eq!(large.id, id);
+                    }
+                    waitpid(child, None).unwrap();
+                }
+                Ok(ForkResult::Child) => {
+                    for id in 0..3 {
+                        sender
+                            .send(Large {
+                                id,
+                                data: [0; 1 << 14],
+                            })
+                            .unwrap();
+                    }
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
+
+        #[test]
+        pub fn recv_ref_copies_misaligned_payload() {
+            // more alignment than the control block in front of the payload has
+            #[derive(Debug, Clone, Copy, PartialEq)]
+            #[repr(align(64))]
+            struct Aligned(u64);
+            unsafe impl SharedSafe for Aligned {}
+
+            let mut receiver = Receiver::<Aligned>::with_options(ChannelOptions {
+                layout: Layout::OwnerFirst,
+                ..ChannelOptions::default()
+            })
+            .unwrap();
+            receiver.new_sender().send(Aligned(42)).unwrap();
+            let payload = receiver.buffer.buffer().as_ptr();
+            assert!(!payload.cast::<Aligned>().is_aligned());
+
+            let value = receiver.recv_ref().unwrap();
+            assert_ne!(&*value as *const Aligned as *const u8, payload);
+            assert_eq!(*value, Aligned(42));
+            drop(value);
+            assert_eq!(receiver.buffer.current_owner(), SENDER);
+        }
+
+        fn array_round_trip<const N: usize>() {
+            let mut receiver = Receiver::array::<u32, N>().unwrap();
+            let mut sender = receiver.new_sender();
+            let mut data = [0; N];
+            for (i, x) in data.iter_mut().enumerate() {
+                *x = 3 * i as u32 + 1;
+            }
+
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    assert_eq!(receiver.recv().unwrap(), data);
+                    waitpid(child, None).unwrap();
+                }
+                Ok(ForkResult::Child) => {
+                    sender.send(data).unwrap();
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
+
+        #[test]
+        pub fn const_size_arrays() {
+            array_round_trip::<1>();
+            array_round_trip::<8>();
+            array_round_trip::<64>();
+        }
+
+        fn blocked_recv_sleeps(wait_policy: WaitPolicy) {
+            use nix::libc::CLOCK_THREAD_CPUTIME_ID;
+
+            let mut receiver = Receiver::<u64>::with_policy(wait_policy).unwrap();
+            let mut sender = receiver.new_sender();
+
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    let cpu_start = cpu_time(CLOCK_THREAD_CPUTIME_ID);
+                    let wall_start = Instant::now();
+                    assert_eq!(receiver.recv().unwrap(), 42);
+                    assert!(wall_start.elapsed() >= Duration::from_millis(200));
+                    let cpu = cpu_time(CLOCK_THREAD_CPUTIME_ID) - cpu_start;
+                    assert!(cpu < Duration::from_millis(20));
+                    waitpid(child, None).unwrap();
+                }
+                Ok(ForkResult::Child) => {
+                    std::thread::sleep(Duration::from_millis(300));
+                    sender.send(42).unwrap();
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
+
+        #[test]
+        #[cfg(target_os = "linux")]
+        pub fn eventfd_recv_sleeps() {
+            blocked_recv_sleeps(WaitPolicy::EventFd);
+        }
+
+        #[test]
+        #[cfg(target_os = "linux")]
+        pub fn eventfd_wakes_epoll() {
+            use nix::sys::epoll::*;
+
+            let mut receiver = Receiver::<u64>::with_policy(WaitPolicy::EventFd).unwrap();
+            assert_eq!(Receiver::<u64>::new().unwrap().as_raw_fd(), None);
+            let fd = receiver.as_raw_fd().unwrap();
+            let mut sender = receiver.new_sender();
+
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    let epoll = epoll_create1(EpollCreateFlags::EPOLL_CLOEXEC).unwrap();
+                    let mut event = EpollEvent::new(EpollFlags::EPOLLIN, 0);
+                    epoll_ctl(epoll, EpollOp::EpollCtlAdd, fd, &mut event).unwrap();
+                    let mut events = [EpollEvent::empty()];
+                    for i in 0..3 {
+                        assert_eq!(epoll_wait(epoll, &mut events, 5000).unwrap(), 1);
+                        assert_eq!(receiver.try_recv().unwrap(), Some(i));
+                        assert_eq!(receiver.try_recv().unwrap(), None);
+                    }
+                    // drained, so the descriptor doesn't stay readable
+                    assert_eq!(epoll_wait(epoll, &mut events, 0).unwrap(), 0);
+                    close(epoll).unwrap();
+                    waitpid(child, None).unwrap();
+                }
+                Ok(ForkResult::Child) => {
+                    for i in 0..3 {
+                        std::thread::sleep(Duration::from_millis(50));
+                        sender.send(i).unwrap();
+                    }
+                    std::process::exit(0);
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
+
+        // elsewhere the futex fallback naps in a loop, which costs some CPU time
+        #[test]
+        #[cfg(target_os = "linux")]
+        pub fn futex_recv_sleeps() {
+            blocked_recv_sleeps(WaitPolicy::Futex);
+        }
+    }
+
+    /// CPU time consumed so far according to `clock`
+    fn cpu_time(clock: nix::libc::clockid_t) -> Duration {
+        use nix::libc::{clock_gettime, timespec};
+        let mut t = timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe { clock_gettime(clock, &mut t) };
+        Duration::new(t.tv_sec as u64, t.tv_nsec as u32)
+    }
+
+    /// Compares the wait policies with twice as many channels as there are cores, each with
+    /// its own sending and receiving process, so that every core is shared by four processes
+    pub fn bench_oversubscribed() {
+        use nix::sys::wait::waitpid;
+
+        const MESSAGES: usize = 2_000;
+        let pairs = 2 * std::thread::available_parallelism().map_or(1, |n| n.get());
+
+        for &wait_policy in &[
+            WaitPolicy::Spin,
+            WaitPolicy::SpinThenYield(Backoff::default()),
+            WaitPolicy::Futex,
+        ] {
+            let t1 = Instant::now();
+            let mut children = Vec::new();
+            for _ in 0..pairs {
+                let mut receiver = Receiver::<[u8; 64]>::with_policy(wait_policy).unwrap();
+                let mut sender = receiver.new_sender();
+                match fork() {
+                    Ok(ForkResult::Parent { child, .. }) => children.push(child),
+                    Ok(ForkResult::Child) => {
+                        for _ in 0..MESSAGES {
+                            sender.send_unchecked([0; 64]);
+                        }
+                        std::process::exit(0);
+                    }
+                    Err(_) => panic!("Fork failed"),
+                }
+                match fork() {
+                    Ok(ForkResult::Parent { child, .. }) => children.push(child),
+                    Ok(ForkResult::Child) => {
+                        for _ in 0..MESSAGES {
+                            let _dat = receiver.recv().unwrap();
+                        }
+                        std::process::exit(0);
+                    }
+                    Err(_) => panic!("Fork failed"),
+                }
+            }
+            for child in children {
+                waitpid(child, None).unwrap();
+            }
+            let wall = t1.elapsed();
+            println!(
+                "policy: {:?}, pairs: {}, wall time: {:?}, throughput: {:e}messages/s",
+                wait_policy,
+                pairs,
+                wall,
+                (pairs * MESSAGES) as f64 / wall.as_secs_f64()
+            );
+        }
+    }
+
+    /// Compares the wait policies on traffic that is mostly idle with occasional bursts,
+    /// where spinning wastes the most CPU time
+    pub fn bench_idle_burst() {
+        use nix::libc::CLOCK_PROCESS_CPUTIME_ID;
+        use nix::sys::wait::waitpid;
+
+        const BURSTS: usize = 10;
+        const BURST_LEN: usize = 10_000;
+        const IDLE: Duration = Duration::from_millis(50);
+
+        for &wait_policy in &[WaitPolicy::Spin, WaitPolicy::Futex, WaitPolicy::EventFd] {
+            let mut receiver = match Receiver::<[u8; 64]>::with_policy(wait_policy) {
+                Ok(receiver) => receiver,
+                Err(e) => {
+                    println!("Skipping {:?}: {}", wait_policy, e);
+                    continue;
+                }
+            };
+            let mut sender = receiver.new_sender();
+            match fork() {
+                Ok(ForkResult::Parent { child, .. }) => {
+                    let cpu_start = cpu_time(CLOCK_PROCESS_CPUTIME_ID);
                     let t1 = Instant::now();
-                    for _ in 0..IMAX {
-                        sender.send(buf);
+                    for _ in 0..BURSTS * BURST_LEN {
+                        let _dat = receiver.recv().unwrap();
                     }
-                    let t2 = Instant::now() - t1;
-                    times.push((BUFFER_SIZE, t2));
-                }
-
-                for (message_length, t2) in times {
+                    let wall = t1.elapsed();
+                    let cpu = cpu_time(CLOCK_PROCESS_CPUTIME_ID) - cpu_start;
+                    waitpid(child, None).unwrap();
                     println!(
-                        "Tx, pid: {:?}, length: {:-6}, time: {:?}, latency: {:?}, bandwith: {:e}byte/s",
-                        pid,
-                        message_length,
-                        t2,
-                        t2.checked_div(IMAX as u32).unwrap(),
-                        10.0f64.powf(9.0) * (message_length * IMAX) as f64 / t2.as_nanos() as f64
+                        "Rx, policy: {:?}, wall time: {:?}, cpu time: {:?}, busy: {:.1}%",
+                        wait_policy,
+                        wall,
+                        cpu,
+                        100.0 * cpu.as_secs_f64() / wall.as_secs_f64()
                     );
                 }
-                println!("Child shutting down");
+                Ok(ForkResult::Child) => {
+                    for _ in 0..BURSTS {
+                        std::thread::sleep(IDLE);
+                        for _ in 0..BURST_LEN {
+                            sender.send_unchecked([0; 64]);
+                        }
+                    }
+                    std::process::exit(0);
+                }
+                Err(_) => panic!("Fork failed"),
             }
-            Err(_) => panic!("Fork failed"),
         }
     }
 
@@ -377,57 +2782,600 @@ mod channel {
     }
 }
 
+/// Kills every rank of the group, including the calling one, and exits with `code`, like
+/// `MPI_Abort`. Any rank can call it, not just the root.
+///
+/// The other ranks get `SIGKILL`, so they can't clean up after themselves.
+pub fn abort(comm: &Communicator, code: i32) -> ! {
+    use nix::sys::signal::{self, kill};
+
+    for (rank, pid) in comm.pids().iter().enumerate() {
+        let pid = pid.load(Ordering::Relaxed);
+        // a pid of 0 would kill the whole process group of the caller
+        if rank != comm.rank && pid != 0 {
+            let _ = kill(Pid::from_raw(pid), signal::SIGKILL);
+        }
+    }
+    std::process::exit(code)
+}
+
+/// Ends the group, the counterpart of `init`: every rank waits for the ranks it forked. The
+/// root then returns 0 if all other ranks exited successfully, or otherwise the exit code of
+/// one that didn't. All other ranks exit with that aggregate status of their part of the
+/// group instead of returning.
+///
+/// Ranks that were killed by a signal count as exit code 128 + signal number, like in a shell.
+/// Ranks that already exited are fine, but ones that were reaped elsewhere, e.g. with
+/// `wait_for_process`, can't report anything and count as successful.
+pub fn finalize(comm: Communicator) -> i32 {
+    let mut status = 0;
+    for &child in comm.children.iter() {
+        let code = match waitpid(child, None) {
+            Ok(WaitStatus::Exited(_, code)) => code,
+            Ok(WaitStatus::Signaled(_, signal, _)) => 128 + signal as i32,
+            _ => 0,
+        };
+        if status == 0 {
+            status = code;
+        }
+    }
+    if comm.rank != 0 {
+        std::process::exit(status);
+    }
+    status
+}
+
 pub fn kill_process(process: &Process) {
     if !process.kill(Signal::Abort) {
         process.kill(Signal::Kill);
     }
 }
 
+/// Blocks until the child process `pid` exits and reaps it.
+///
+/// With a timeout, `action` is called on the process if it's still running once the timeout
+/// passed, e.g. `kill_process`, and the function returns without waiting any longer. Returns
+/// immediately if `pid` isn't a child of the calling process.
 pub fn wait_for_process<F: FnOnce(&Process)>(pid: Pid, timeout: Option<(Duration, F)>) {
-    let mut sys = System::new();
-    sys.refresh_all();
-    let t1 = Instant::now();
-    if let Some(p) = sys.get_process(i32::from(pid)) {
-        match timeout {
-            Some((timeout, action)) => {
-                while p.status().to_string() != "Zombie" {
-                    // yup, this is shit code.
-                    if (Instant::now() - t1) >= timeout {
-                        action(&p);
-                        break;
+    match timeout {
+        Some((timeout, action)) => {
+            let t1 = Instant::now();
+            // anything but StillAlive means the child is gone or was never ours
+            while let Ok(WaitStatus::StillAlive) = waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+                if t1.elapsed() >= timeout {
+                    let mut sys = System::new();
+                    sys.refresh_process(i32::from(pid));
+                    if let Some(p) = sys.get_process(i32::from(pid)) {
+                        action(p);
                     }
+                    // reap it right away if the action ended it
+                    let _ = waitpid(pid, Some(WaitPidFlag::WNOHANG));
+                    break;
                 }
+                std::thread::sleep(Duration::from_millis(1));
             }
-            None => while p.status().to_string() != "Zombie" {},
+        }
+        None => {
+            let _ = waitpid(pid, None);
         }
     }
 }
 
+/// The process group spawned by `init`, through which every rank can reach every other one,
+/// like an `MPI_Comm`.
+///
+/// The ranks are connected by `L`, shared memory links between forked processes by default.
+/// See `Communicator::threads` for a group of threads instead.
 #[derive(new)]
-pub struct MpiInformation {
+pub struct Communicator<L = channel::Link> {
     pub n_processes: usize,
     pub rank: usize,
+    epoch: Instant,
+    /// Link from rank `i` to rank `j` at index `i * n_processes + j`. The diagonal is `None`,
+    /// as messages to the own rank go through `to_self`, and a communicator from `split`
+    /// only holds the links of its own rank.
+    links: Vec<Option<RefCell<L>>>,
+    barrier: Arc<barrier::SharedBarrier>,
+    /// Pid of every rank as an `AtomicI32`, indexed by rank, for `abort`
+    pids: MmapMut,
+    /// Ranks this process forked itself, for `finalize`
+    children: Vec<Pid>,
+    /// Messages this rank sent to itself that haven't been received yet
+    #[new(default)]
+    to_self: RefCell<VecDeque<Vec<u8>>>,
+}
+
+impl<L: Transport> Communicator<L> {
+    /// Rank of the calling process, between 0 and `size() - 1`
+    pub fn rank(&self) -> usize {
+        self.rank
+    }
+
+    /// Number of processes in the group
+    pub fn size(&self) -> usize {
+        self.n_processes
+    }
+
+    /// Seconds elapsed since the common epoch of the process group, like `MPI_Wtime`.
+    ///
+    /// The epoch is taken once before any process is forked, so every rank measures from
+    /// the same origin and timestamps can be compared across ranks.
+    pub fn wtime(&self) -> f64 {
+        self.epoch.elapsed().as_secs_f64()
+    }
+
+    fn pids(&self) -> &[AtomicI32] {
+        // the mapping is page aligned and holds `n_processes` pids
+        unsafe {
+            std::slice::from_raw_parts(self.pids.as_ptr() as *const AtomicI32, self.n_processes)
+        }
+    }
+
+    fn link(&self, from: usize, to: usize) -> &RefCell<L> {
+        assert!(
+            from < self.n_processes && to < self.n_processes,
+            "rank {} out of range for {} processes",
+            from.max(to),
+            self.n_processes
+        );
+        self.links[from * self.n_processes + to]
+            .as_ref()
+            .expect("link between two other ranks")
+    }
+
+    /// Sends `data` to rank `dest`. With the default links this blocks until `dest` picked up
+    /// the previous message from this rank, and for messages larger than a page until it has
+    /// received all but the last page of this one.
+    ///
+    /// Sending to the own rank just queues a copy for `recv_from`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dest` isn't a rank of the group.
+    pub fn send_to<T: channel::SharedSafe>(&self, dest: usize, data: T) {
+        self.send_slice_to(dest, std::slice::from_ref(&data));
+    }
+
+    /// Like `send_to`, but sends all elements of `data` as one message. The receiving rank
+    /// has to know how many there are, see `recv_vec_from`. An empty slice sends nothing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dest` isn't a rank of the group.
+    pub fn send_slice_to<T: channel::SharedSafe>(&self, dest: usize, data: &[T]) {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data))
+        };
+        if dest == self.rank {
+            self.to_self.borrow_mut().push_back(bytes.to_vec());
+        } else {
+            self.link(self.rank, dest).borrow_mut().send(bytes);
+        }
+    }
+
+    /// Receives the next value rank `src` sent to this rank, blocking until there is one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `src` isn't a rank of the group, or if it's the own rank and there is no
+    /// message from `send_to` waiting - nobody else could ever send one.
+    pub fn recv_from<T: channel::SharedSafe>(&self, src: usize) -> T {
+        let mut value = MaybeUninit::<T>::uninit();
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, std::mem::size_of::<T>())
+        };
+        if src == self.rank {
+            let message = self
+                .to_self
+                .borrow_mut()
+                .pop_front()
+                .expect("recv_from the own rank without a message sent to it");
+            bytes.copy_from_slice(&message);
+        } else {
+            self.link(src, self.rank).borrow_mut().recv(bytes);
+        }
+        // every byte was overwritten with those of a `T` from the sending rank
+        unsafe { value.assume_init() }
+    }
+
+    /// Receives a message of `len` elements that rank `src` sent with `send_slice_to`. With
+    /// a `len` of 0 this returns right away.
+    ///
+    /// # Panics
+    ///
+    /// Same as `recv_from`.
+    pub fn recv_vec_from<T: channel::SharedSafe>(&self, src: usize, len: usize) -> Vec<T> {
+        let mut values = Vec::<T>::with_capacity(len);
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(
+                values.as_mut_ptr() as *mut u8,
+                len * std::mem::size_of::<T>(),
+            )
+        };
+        if src == self.rank {
+            let message = self
+                .to_self
+                .borrow_mut()
+                .pop_front()
+                .expect("recv_vec_from the own rank without a message sent to it");
+            bytes.copy_from_slice(&message);
+        } else {
+            self.link(src, self.rank).borrow_mut().recv(bytes);
+        }
+        // the first `len` elements were overwritten with those from the sending rank
+        unsafe { values.set_len(len) };
+        values
+    }
+}
+
+/// Number of `Communicator::split` calls in this process, to tell apart the files of
+/// different calls
+static SPLITS: AtomicUsize = AtomicUsize::new(0);
+
+/// Makes every rank of `comm` fail if `result` is an error on any of them, so that a step of
+/// `split` either succeeds or fails as a whole. Also waits until every rank got that far.
+fn agree<T>(comm: &Communicator, result: std::io::Result<T>) -> std::io::Result<T> {
+    if collective::allreduce(comm, result.is_ok(), |a, b| a && b) {
+        return result;
+    }
+    result.and_then(|_| Err(std::io::Error::other("split failed on another rank")))
+}
+
+impl Communicator {
+    /// Partitions the group into subgroups, like `MPI_Comm_split`: all ranks passing the same
+    /// `color` end up in the same new communicator, ordered by `key` and then by their rank
+    /// in this one. Every rank of this group has to call `split`.
+    ///
+    /// The ranks are forked already, so the links of a subgroup can't be shared by forking.
+    /// Instead they are files in `/dev/shm` (or the temporary directory where that doesn't
+    /// exist), which are removed again once every rank has mapped them. If creating or
+    /// opening them fails on any rank, all ranks remove their files and return an error.
+    ///
+    /// `abort` on the new communicator only kills the ranks of the subgroup, and `finalize`
+    /// doesn't wait for anything - call it on the original communicator.
+    pub fn split(&self, color: usize, key: usize) -> std::io::Result<Communicator> {
+        let nonce = SPLITS.fetch_add(1, Ordering::Relaxed);
+        let own = (color, key, getpid().as_raw(), nonce);
+        let all = collective::alltoall(self, &vec![own; self.n_processes]);
+
+        let mut members: Vec<usize> = (0..self.n_processes)
+            .filter(|&rank| all[rank].0 == color)
+            .collect();
+        members.sort_by_key(|&rank| (all[rank].1, rank));
+        let n = members.len();
+        let rank = members.iter().position(|&r| r == self.rank).unwrap();
+
+        let dir = if std::path::Path::new("/dev/shm").is_dir() {
+            PathBuf::from("/dev/shm")
+        } else {
+            env::temp_dir()
+        };
+        // files are named after the rank creating them
+        let path = |creator: usize, name: String| {
+            let (_, _, pid, nonce) = all[members[creator]];
+            dir.join(format!("mpi2-{}-{}-{}", pid, nonce, name))
+        };
+        let link_path = |from: usize, to: usize| path(to, format!("link-{}", from));
+        let barrier_path = path(0, "barrier".to_string());
+
+        // every rank creates its incoming links, and the first one the barrier
+        let mut created = Vec::new();
+        let incoming = (|| -> std::io::Result<_> {
+            let mut incoming = Vec::new();
+            for from in 0..n {
+                if from != rank {
+                    created.push(link_path(from, rank));
+                    incoming.push(channel::Link::create_named(&link_path(from, rank))?);
+                }
+            }
+            let barrier = if rank == 0 {
+                created.push(barrier_path.clone());
+                Some(barrier::SharedBarrier::create_named(&barrier_path, n)?)
+            } else {
+                None
+            };
+            Ok((incoming, barrier))
+        })();
+
+        let attached = agree(self, incoming).and_then(|(incoming, barrier)| {
+            let outgoing = (|| -> std::io::Result<_> {
+                let mut outgoing = Vec::new();
+                for to in 0..n {
+                    if to != rank {
+                        outgoing.push(channel::Link::open_named(&link_path(rank, to))?);
+                    }
+                }
+                let barrier = match barrier {
+                    Some(barrier) => barrier,
+                    None => barrier::SharedBarrier::open_named(&barrier_path, n)?,
+                };
+                Ok((outgoing, barrier))
+            })();
+            agree(self, outgoing).map(|(outgoing, barrier)| (incoming, outgoing, barrier))
+        });
+        // everyone is attached or gave up, the mappings keep the memory alive. A file that
+        // can't be removed only takes up space, that's no reason to fail on this rank alone.
+        for path in created {
+            let _ = std::fs::remove_file(path);
+        }
+        let (incoming, outgoing, barrier) = attached?;
+
+        let mut incoming = incoming.into_iter();
+        let mut outgoing = outgoing.into_iter();
+        let mut links = Vec::with_capacity(n * n);
+        for from in 0..n {
+            for to in 0..n {
+                let link = if to == rank && from != rank {
+                    incoming.next()
+                } else if from == rank && to != rank {
+                    outgoing.next()
+                } else {
+                    // not used by this rank
+                    None
+                };
+                links.push(link.map(RefCell::new));
+            }
+        }
+
+        let mut pids = MmapOptions::new()
+            .len(n.max(1) * std::mem::size_of::<i32>())
+            .map_anon()?;
+        let pid_slots = unsafe { std::slice::from_raw_parts_mut(pids.as_mut_ptr() as *mut i32, n) };
+        for (slot, &member) in pid_slots.iter_mut().zip(members.iter()) {
+            *slot = self.pids()[member].load(Ordering::SeqCst);
+        }
+
+        Ok(Communicator::new(
+            n,
+            rank,
+            self.epoch,
+            links,
+            Arc::new(barrier),
+            pids,
+            Vec::new(),
+        ))
+    }
+}
+
+/// Binds the calling process to a single core, chosen as `rank` modulo the number of cores it
+/// may run on. With fewer cores than ranks several ranks share a core.
+///
+/// Keeps the scheduler from moving ranks between cores, which makes benchmark numbers a lot
+/// more stable. This is a no-op on platforms without affinity support, like macOS.
+pub fn pin_rank(rank: usize) -> std::io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        use nix::sched::{sched_getaffinity, sched_setaffinity, CpuSet};
+
+        let this_process = Pid::from_raw(0);
+        let allowed = sched_getaffinity(this_process).map_err(channel::nix_to_io)?;
+        let cores: Vec<usize> = (0..CpuSet::count())
+            .filter(|&cpu| allowed.is_set(cpu).unwrap_or(false))
+            .collect();
+        // a process always may run somewhere, so `cores` isn't empty
+        let mut pinned = CpuSet::new();
+        pinned
+            .set(cores[rank % cores.len()])
+            .map_err(channel::nix_to_io)?;
+        sched_setaffinity(this_process, &pinned).map_err(channel::nix_to_io)?;
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = rank;
+    Ok(())
+}
+
+/// Why `init` couldn't set up the process group
+#[derive(Debug)]
+pub enum SpawnError {
+    /// The memory shared between the ranks couldn't be set up, so nothing was forked, or a
+    /// rank couldn't be pinned to a core, so all ranks exited again before `init` returned
+    Io(std::io::Error),
+    /// `fork` failed somewhere in the tree, so only `spawned` of the `requested` processes
+    /// were running. They all exited again before `init` returned.
+    Fork {
+        spawned: usize,
+        requested: usize,
+        source: std::io::Error,
+    },
+}
+
+impl std::fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SpawnError::Io(e) => write!(f, "couldn't set up the process group: {}", e),
+            SpawnError::Fork {
+                spawned,
+                requested,
+                source,
+            } => write!(
+                f,
+                "fork failed after spawning {} of {} processes: {}",
+                spawned, requested, source
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SpawnError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SpawnError::Io(e) => Some(e),
+            SpawnError::Fork { source, .. } => Some(source),
+        }
+    }
+}
+
+impl From<std::io::Error> for SpawnError {
+    fn from(e: std::io::Error) -> Self {
+        SpawnError::Io(e)
+    }
+}
+
+/// Bookkeeping of `spawn_processes` shared by the whole tree, living inside an anonymous mmap
+#[repr(C)]
+struct SpawnState {
+    /// Processes that are done forking, successfully or not
+    settled: AtomicUsize,
+    /// Ranks that will never exist because a fork failed
+    missing: AtomicUsize,
+    /// errno of the last failed fork
+    errno: AtomicI32,
+    /// Ranks that couldn't be pinned to a core
+    unpinned: AtomicUsize,
+    /// errno of the last failed pin
+    pin_errno: AtomicI32,
+}
+
+fn spawn_processes(n: usize, pin: bool) -> Result<Communicator, SpawnError> {
+    let pin = |rank| if pin { pin_rank(rank) } else { Ok(()) };
+    spawn_processes_with(n, pin, fork)
 }
 
-fn spawn_processes(n: usize) -> MpiInformation {
+/// `spawn_processes` with the functions used to pin a rank and to fork, so tests can make
+/// them fail
+fn spawn_processes_with(
+    n: usize,
+    pin: impl Fn(usize) -> std::io::Result<()>,
+    fork: impl Fn() -> nix::Result<ForkResult>,
+) -> Result<Communicator, SpawnError> {
+    // Instant is based on a system wide monotonic clock, so the copy each child inherits
+    // refers to the same point in time for all ranks
+    let epoch = Instant::now();
+    // all links have to exist before forking so that every rank shares them
+    let links = (0..n * n)
+        .map(|i| {
+            if i / n == i % n {
+                // messages to the own rank don't need a link
+                return Ok(None);
+            }
+            channel::Link::new().map(|link| Some(RefCell::new(link)))
+        })
+        .collect::<std::io::Result<_>>()?;
+    let barrier = Arc::new(barrier::SharedBarrier::new(n)?);
+    // the mapping is zeroed, which is a valid `SpawnState`
+    let mut state_mmap = MmapOptions::new()
+        .len(std::mem::size_of::<SpawnState>())
+        .map_anon()?;
+    let state = unsafe { &*(state_mmap.as_mut_ptr() as *const SpawnState) };
+    // filled in by every rank once it exists
+    let mut pids = MmapOptions::new()
+        .len(n.max(1) * std::mem::size_of::<AtomicI32>())
+        .map_anon()?;
+
+    // every process spawns the ranks `rank + 1..=rank + procs_to_create`
     let mut rank = 0;
-    let mut procs_to_create = n;
+    let mut procs_to_create = n.saturating_sub(1);
+    let mut children = Vec::new();
     while procs_to_create != 0 {
-        procs_to_create -= 1;
+        // the child takes over the upper half of the remaining ranks
         let child_procs = procs_to_create / 2;
+        let child_rank = rank + procs_to_create - child_procs;
         match fork() {
             Ok(ForkResult::Child) => {
                 procs_to_create = child_procs;
-                rank += child_procs + 1;
+                rank = child_rank;
+                children.clear();
             }
-            Ok(ForkResult::Parent { .. }) => procs_to_create -= child_procs,
-            Err(_) => panic!("Fork failed - couldn't spawn process."),
+            Ok(ForkResult::Parent { child }) => {
+                procs_to_create -= child_procs + 1;
+                children.push(child);
+            }
+            Err(e) => {
+                // none of the ranks this process was responsible for will show up
+                let errno = match e {
+                    nix::Error::Sys(errno) => errno as i32,
+                    _ => nix::errno::Errno::UnknownErrno as i32,
+                };
+                state.errno.store(errno, Ordering::Relaxed);
+                state.missing.fetch_add(procs_to_create, Ordering::AcqRel);
+                break;
+            }
+        }
+    }
+
+    if rank < n {
+        let own_pid = unsafe { &*(pids.as_mut_ptr() as *const AtomicI32).add(rank) };
+        own_pid.store(getpid().as_raw(), Ordering::Relaxed);
+        if let Err(e) = pin(rank) {
+            let errno = e
+                .raw_os_error()
+                .unwrap_or(nix::errno::Errno::UnknownErrno as i32);
+            state.pin_errno.store(errno, Ordering::Relaxed);
+            state.unpinned.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+    // only once every process settled it's known whether the whole group exists, and all
+    // ranks that exist have published their pid
+    state.settled.fetch_add(1, Ordering::AcqRel);
+    while state.settled.load(Ordering::Acquire) + state.missing.load(Ordering::Acquire) < n {
+        std::thread::yield_now();
+    }
+    let missing = state.missing.load(Ordering::Acquire);
+    let unpinned = state.unpinned.load(Ordering::Acquire);
+    if missing > 0 || unpinned > 0 {
+        // tear the partial tree down bottom up, so no process is left behind as an orphan
+        // or a zombie
+        for child in children {
+            let _ = waitpid(child, None);
         }
+        if rank != 0 {
+            std::process::exit(0);
+        }
+        if missing == 0 {
+            return Err(SpawnError::Io(std::io::Error::from_raw_os_error(
+                state.pin_errno.load(Ordering::Relaxed),
+            )));
+        }
+        return Err(SpawnError::Fork {
+            spawned: n - missing,
+            requested: n,
+            source: std::io::Error::from_raw_os_error(state.errno.load(Ordering::Relaxed)),
+        });
+    }
+
+    RANK.store(rank, Ordering::Relaxed);
+    WORLD_SIZE.store(n, Ordering::Relaxed);
+    Ok(Communicator::new(
+        n, rank, epoch, links, barrier, pids, children,
+    ))
+}
+
+/// Rank and size of the group spawned by `init`, `usize::MAX` before that. Every forked
+/// process has its own copy.
+static RANK: AtomicUsize = AtomicUsize::new(usize::MAX);
+static WORLD_SIZE: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Rank of the calling process in the group spawned by `init`, for code that doesn't have
+/// the `Communicator` at hand.
+///
+/// # Panics
+///
+/// Panics if the group hasn't been spawned yet.
+pub fn current_rank() -> usize {
+    match RANK.load(Ordering::Relaxed) {
+        usize::MAX => panic!("MPI not initialized, call init first"),
+        rank => rank,
+    }
+}
+
+/// Number of processes in the group spawned by `init`, see `current_rank`.
+///
+/// # Panics
+///
+/// Panics if the group hasn't been spawned yet.
+pub fn world_size() -> usize {
+    match WORLD_SIZE.load(Ordering::Relaxed) {
+        usize::MAX => panic!("MPI not initialized, call init first"),
+        n => n,
     }
-    MpiInformation::new(n, rank)
 }
 
-pub fn init() -> MpiInformation {
+/// Spawns the process group and returns the calling process' handle to it. The group size is
+/// taken from the `-n` argument (8 by default) and `--pin` binds every rank to a core.
+///
+/// If not all processes can be forked, the ones that were are shut down again and only the
+/// original process returns, with the error.
+pub fn init() -> Result<Communicator, SpawnError> {
     const DEFAULT_N: usize = 8;
     let args: Vec<String> = env::args().collect();
     let n = args
@@ -439,16 +3387,449 @@ pub fn init() -> MpiInformation {
                 .expect("Expected valid number as value for -n argument.")
         })
         .unwrap_or(DEFAULT_N);
-    spawn_processes(n)
+    // pin every rank to its own core, wrapping around if there are more ranks than cores
+    let pin = args.iter().any(|s| s == "--pin");
+    spawn_processes(n, pin)
 }
 
 #[cfg(test)]
 pub mod tests {
     #[allow(unused_imports)]
     use super::*;
+    use channel::Receiver;
+
+    use std::collections::BTreeSet;
+    use std::mem::size_of;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use memmap::MmapOptions;
+
+    #[test]
+    fn wtime_has_common_origin() {
+        let mut receiver = Receiver::<f64>::new().unwrap();
+        let mut sender = receiver.new_sender();
+        let comm = spawn_processes(2, false).unwrap();
+        match comm.rank {
+            0 => {
+                let remote = receiver.recv().unwrap();
+                let local = comm.wtime();
+                assert!(remote >= 0.0);
+                assert!(local >= remote);
+                assert!(local - remote < 1.0);
+            }
+            _ => {
+                sender.send(comm.wtime()).unwrap();
+                std::process::exit(0);
+            }
+        }
+    }
+
+    #[test]
+    fn fork_send_join() {
+        let (mut sender, mut receiver) = channel::channel::<u64>().unwrap();
+        match fork() {
+            Ok(ForkResult::Parent { child, .. }) => {
+                assert_eq!(receiver.recv().unwrap(), 42);
+                wait_for_process(child, Some((Duration::from_secs(10), &kill_process)));
+            }
+            Ok(ForkResult::Child) => {
+                sender.send(42).unwrap();
+                std::process::exit(0);
+            }
+            Err(e) => panic!("fork failed: {}", e),
+        }
+    }
+
+    #[test]
+    fn wait_for_process_returns_on_exit() {
+        match fork() {
+            Ok(ForkResult::Parent { child, .. }) => {
+                let t1 = Instant::now();
+                wait_for_process(child, Some((Duration::from_secs(10), &kill_process)));
+                assert!(t1.elapsed() < Duration::from_secs(5));
+                // the child was reaped
+                assert!(waitpid(child, Some(WaitPidFlag::WNOHANG)).is_err());
+            }
+            Ok(ForkResult::Child) => {
+                std::thread::sleep(Duration::from_millis(50));
+                std::process::exit(0);
+            }
+            Err(e) => panic!("fork failed: {}", e),
+        }
+    }
+
+    #[test]
+    fn wait_for_process_times_out() {
+        match fork() {
+            Ok(ForkResult::Parent { child, .. }) => {
+                let mut fired = false;
+                let t1 = Instant::now();
+                wait_for_process(
+                    child,
+                    Some((Duration::from_millis(100), |p: &Process| {
+                        fired = true;
+                        p.kill(Signal::Kill);
+                    })),
+                );
+                assert!(fired);
+                assert!(t1.elapsed() >= Duration::from_millis(100));
+                let _ = waitpid(child, None);
+            }
+            Ok(ForkResult::Child) => {
+                std::thread::sleep(Duration::from_secs(10));
+                std::process::exit(0);
+            }
+            Err(e) => panic!("fork failed: {}", e),
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn pin_rank_wraps_around() {
+        use nix::sched::{sched_getaffinity, CpuSet};
+
+        // pin in a child, so the test process keeps its affinity
+        match fork() {
+            Ok(ForkResult::Parent { child, .. }) => {
+                assert_eq!(waitpid(child, None).unwrap(), WaitStatus::Exited(child, 0));
+            }
+            Ok(ForkResult::Child) => {
+                // far more ranks than any machine has cores
+                let pinned = pin_rank(CpuSet::count() + 3).is_ok();
+                let allowed = sched_getaffinity(Pid::from_raw(0)).unwrap();
+                let cores = (0..CpuSet::count())
+                    .filter(|&cpu| allowed.is_set(cpu).unwrap())
+                    .count();
+                std::process::exit(if pinned && cores == 1 { 0 } else { 1 });
+            }
+            Err(e) => panic!("fork failed: {}", e),
+        }
+    }
+
+    #[test]
+    fn failed_pin_is_an_error() {
+        use nix::errno::Errno;
+
+        const N: usize = 4;
+        let failing_pin = |rank| {
+            if rank == 2 {
+                return Err(std::io::Error::from_raw_os_error(Errno::EINVAL as i32));
+            }
+            Ok(())
+        };
+        match spawn_processes_with(N, failing_pin, fork) {
+            Err(SpawnError::Io(e)) => assert_eq!(e.raw_os_error(), Some(Errno::EINVAL as i32)),
+            Err(e) => panic!("expected a pin error, got {}", e),
+            Ok(comm) => {
+                if comm.rank != 0 {
+                    std::process::exit(1);
+                }
+                panic!("spawning succeeded despite a failing pin");
+            }
+        }
+    }
+
+    #[test]
+    fn failed_fork_is_an_error() {
+        use nix::errno::Errno;
+        use nix::sys::signal::kill;
+
+        const N: usize = 8;
+        const FORKS: usize = 3;
+        // forks left before failing and the pids of all children, shared by the whole tree
+        let mut mmap = MmapOptions::new()
+            .len((1 + FORKS) * size_of::<AtomicUsize>())
+            .map_anon()
+            .unwrap();
+        let shared = unsafe {
+            std::slice::from_raw_parts(mmap.as_mut_ptr() as *const AtomicUsize, 1 + FORKS)
+        };
+        let (budget, pids) = shared.split_first().unwrap();
+        budget.store(FORKS, Ordering::SeqCst);
+        let limited_fork = || {
+            let left = budget
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                    left.checked_sub(1)
+                })
+                .map_err(|_| nix::Error::Sys(Errno::EAGAIN))?;
+            let result = fork();
+            if let Ok(ForkResult::Parent { child }) = result {
+                pids[FORKS - left].store(child.as_raw() as usize, Ordering::SeqCst);
+            }
+            result
+        };
+
+        match spawn_processes_with(N, |_| Ok(()), limited_fork) {
+            Err(SpawnError::Fork {
+                spawned,
+                requested,
+                source,
+            }) => {
+                assert_eq!((spawned, requested), (FORKS + 1, N));
+                assert_eq!(source.raw_os_error(), Some(Errno::EAGAIN as i32));
+            }
+            Err(e) => panic!("expected a fork error, got {}", e),
+            Ok(comm) => {
+                if comm.rank != 0 {
+                    std::process::exit(1);
+                }
+                panic!("spawning succeeded despite failing forks");
+            }
+        }
+        // every child is gone for good, not even a zombie is left
+        for pid in pids {
+            let pid = Pid::from_raw(pid.load(Ordering::SeqCst) as i32);
+            assert_eq!(kill(pid, None), Err(nix::Error::Sys(Errno::ESRCH)));
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn abort_kills_all_ranks() {
+        use nix::errno::Errno;
+        use nix::libc::{prctl, PR_SET_CHILD_SUBREAPER};
+        use nix::sys::signal::kill;
+
+        const N: usize = 4;
+        // filled in by the ranks, so the test can check on them afterwards
+        let mut mmap = MmapOptions::new()
+            .len(N * size_of::<AtomicI32>())
+            .map_anon()
+            .unwrap();
+        let pids = unsafe { std::slice::from_raw_parts(mmap.as_mut_ptr() as *const AtomicI32, N) };
+
+        // aborting takes down rank 0 as well, so the group can't be spawned from the test
+        // process itself. The reaper in between collects the ranks orphaned by that.
+        let reaper = match fork() {
+            Ok(ForkResult::Parent { child }) => child,
+            Ok(ForkResult::Child) => {
+                unsafe { prctl(PR_SET_CHILD_SUBREAPER, 1) };
+                if let Ok(ForkResult::Child) = fork() {
+                    let comm = spawn_processes(N, false).unwrap();
+                    pids[comm.rank].store(getpid().as_raw(), Ordering::SeqCst);
+                    collective::barrier(&comm);
+                    if comm.rank == 3 {
+                        abort(&comm, 3);
+                    }
+                    loop {
+                        std::thread::sleep(Duration::from_secs(1));
+                    }
+                }
+                while waitpid(None, None).is_ok() {}
+                std::process::exit(0);
+            }
+            Err(e) => panic!("fork failed: {}", e),
+        };
+
+        let t1 = Instant::now();
+        wait_for_process(reaper, Some((Duration::from_secs(10), &kill_process)));
+        assert!(t1.elapsed() < Duration::from_secs(5));
+        for pid in pids {
+            let pid = Pid::from_raw(pid.load(Ordering::SeqCst));
+            assert_eq!(kill(pid, None), Err(nix::Error::Sys(Errno::ESRCH)));
+        }
+    }
+
+    #[test]
+    fn finalize_joins_all_ranks() {
+        let comm = spawn_processes(4, false).unwrap();
+        if comm.rank != 0 {
+            let _sum: u64 = (0..1000).sum();
+        }
+        assert_eq!(finalize(comm), 0);
+
+        let comm = spawn_processes(4, false).unwrap();
+        match comm.rank {
+            0 => {
+                // the other ranks are done by now, finalize must not wait for them any longer
+                std::thread::sleep(Duration::from_millis(100));
+                let t1 = Instant::now();
+                assert_eq!(finalize(comm), 3);
+                assert!(t1.elapsed() < Duration::from_secs(1));
+            }
+            // a leaf, so its exit code has to travel up through its parent
+            3 => std::process::exit(3),
+            _ => {
+                finalize(comm);
+            }
+        }
+    }
+
     #[test]
     fn test_rank_numbers() {
-        // call spawn_processes, send ranks back to rank 0 process and check all values there
-        unimplemented!()
+        for &n in [1, 2, 6, 8].iter() {
+            // counts the processes actually spawned, so surplus ones are caught as well
+            let mut mmap = MmapOptions::new()
+                .len(size_of::<AtomicUsize>())
+                .map_anon()
+                .unwrap();
+            let spawned = unsafe { &*(mmap.as_mut_ptr() as *const AtomicUsize) };
+
+            let comm = spawn_processes(n, false).unwrap();
+            spawned.fetch_add(1, Ordering::SeqCst);
+            if comm.rank == 0 {
+                let mut ranks: BTreeSet<usize> = (1..n).map(|src| comm.recv_from(src)).collect();
+                ranks.insert(comm.rank);
+                assert_eq!(ranks, (0..n).collect());
+                assert_eq!(spawned.load(Ordering::SeqCst), n);
+            } else {
+                comm.send_to(0, comm.rank);
+                std::process::exit(0);
+            }
+        }
+    }
+
+    #[test]
+    fn ring_exchange() {
+        let comm = spawn_processes(4, false).unwrap();
+        let size = comm.size();
+        comm.send_to((comm.rank() + 1) % size, comm.rank() as u64 * 10);
+        let got: u64 = comm.recv_from((comm.rank() + size - 1) % size);
+
+        if comm.rank() == 0 {
+            assert_eq!(got, 30);
+            for src in 1..size {
+                assert_eq!(comm.recv_from::<u64>(src), (src as u64 - 1) * 10);
+            }
+        } else {
+            comm.send_to(0, got);
+            std::process::exit(0);
+        }
+    }
+
+    #[test]
+    fn split_by_color() {
+        const N: usize = 4;
+        let comm = spawn_processes(N, false).unwrap();
+        // reversed keys, so the local order is the opposite of the global one
+        let sub = comm.split(comm.rank % 2, N - comm.rank).unwrap();
+        let sum = collective::allreduce(&sub, comm.rank, |a, b| a + b);
+        collective::barrier(&sub);
+        let pid_ok = sub.pids()[sub.rank()].load(Ordering::SeqCst) == getpid().as_raw();
+
+        let result = (sub.size(), sub.rank(), sum, pid_ok);
+        if comm.rank == 0 {
+            let expected = [
+                (2, 1, 2, true),
+                (2, 1, 4, true),
+                (2, 0, 2, true),
+                (2, 0, 4, true),
+            ];
+            assert_eq!(result, expected[0]);
+            for (rank, &expected) in expected.iter().enumerate().skip(1) {
+                assert_eq!(
+                    comm.recv_from::<(usize, usize, usize, bool)>(rank),
+                    expected
+                );
+            }
+        } else {
+            comm.send_to(0, result);
+            std::process::exit(0);
+        }
+    }
+
+    #[test]
+    fn failed_split_fails_everywhere() {
+        const N: usize = 3;
+        let comm = spawn_processes(N, false).unwrap();
+        let dir = if std::path::Path::new("/dev/shm").is_dir() {
+            PathBuf::from("/dev/shm")
+        } else {
+            env::temp_dir()
+        };
+        // a directory where rank 2 wants to create its link from rank 0. Not on rank 0, which
+        // shares `SPLITS` with the other tests.
+        let prefix = format!("mpi2-{}-{}-", getpid(), SPLITS.load(Ordering::Relaxed));
+        let blocker = dir.join(format!("{}link-0", prefix));
+        if comm.rank == 2 {
+            std::fs::create_dir(&blocker).unwrap();
+        }
+        collective::barrier(&comm);
+
+        let failed = comm.split(0, comm.rank).is_err();
+        if comm.rank == 2 {
+            std::fs::remove_dir(&blocker).unwrap();
+        }
+        let leftovers = std::fs::read_dir(&dir)
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_string_lossy().starts_with(&prefix)
+            })
+            .count();
+        // the group is still in a usable state
+        let sub = comm.split(0, comm.rank).unwrap();
+        let sum = collective::allreduce(&sub, sub.rank(), |a, b| a + b);
+
+        let ok = failed && leftovers == 0 && sum == 3;
+        if comm.rank == 0 {
+            assert!(ok, "{} {} {}", failed, leftovers, sum);
+            for rank in 1..N {
+                assert!(comm.recv_from::<bool>(rank), "rank {} went wrong", rank);
+            }
+        } else {
+            comm.send_to(0, ok);
+            std::process::exit(0);
+        }
+    }
+
+    #[test]
+    fn global_rank_and_size() {
+        fn identity() -> (usize, usize) {
+            (current_rank(), world_size())
+        }
+
+        let comm = spawn_processes(3, false).unwrap();
+        if comm.rank == 0 {
+            // the original process is shared with tests spawning other group sizes
+            assert_eq!(current_rank(), 0);
+            for rank in 1..3 {
+                assert!(comm.recv_from::<bool>(rank), "rank {} is wrong", rank);
+            }
+        } else {
+            comm.send_to(0, identity() == (comm.rank, 3));
+            std::process::exit(0);
+        }
+    }
+
+    #[test]
+    fn global_rank_before_init_panics() {
+        fn panic_message(f: fn() -> usize) -> Option<String> {
+            let payload = std::panic::catch_unwind(f).err()?;
+            payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+        }
+
+        match fork() {
+            Ok(ForkResult::Parent { child, .. }) => {
+                assert_eq!(waitpid(child, None).unwrap(), WaitStatus::Exited(child, 0));
+            }
+            Ok(ForkResult::Child) => {
+                // other tests may have spawned a group from this process already
+                RANK.store(usize::MAX, Ordering::Relaxed);
+                WORLD_SIZE.store(usize::MAX, Ordering::Relaxed);
+                std::panic::set_hook(Box::new(|_| {}));
+                let uninitialized = [panic_message(current_rank), panic_message(world_size)]
+                    .iter()
+                    .all(|message| {
+                        message
+                            .as_ref()
+                            .is_some_and(|m| m.contains("MPI not initialized"))
+                    });
+                std::process::exit(if uninitialized { 0 } else { 1 });
+            }
+            Err(e) => panic!("fork failed: {}", e),
+        }
+    }
+
+    #[test]
+    fn send_to_self_is_a_local_copy() {
+        let comm = spawn_processes(1, false).unwrap();
+        comm.send_to(0, 1u32);
+        comm.send_to(0, 2u32);
+        assert_eq!(comm.recv_from::<u32>(0), 1);
+        assert_eq!(comm.recv_from::<u32>(0), 2);
     }
 }
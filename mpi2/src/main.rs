@@ -1,8 +1,8 @@
-mod lib;
-
 fn main() {
-    //lib::bench_data_rate();
     //println!("Servo:");
-    //lib::bench_data_rate_servo();
-    lib::init();
+    //mpi2::channel::bench_data_rate_servo();
+    //mpi2::channel::bench_idle_burst();
+    //mpi2::channel::bench_oversubscribed();
+    let comm = mpi2::init().expect("Couldn't spawn processes.");
+    std::process::exit(mpi2::finalize(comm));
 }
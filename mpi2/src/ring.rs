@@ -0,0 +1,202 @@
+use std::io;
+use std::io::{Error, ErrorKind};
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use memmap::{MmapMut, MmapOptions};
+
+use crate::channel::SharedSafe;
+
+/// Read and write positions of a ring, living at the start of its mmap.
+///
+/// Both count messages since the ring was created and only ever grow (wrapping around on
+/// overflow), so `tail - head` is the number of messages in flight.
+#[repr(C)]
+struct RingIndices {
+    /// Number of messages received so far, only written by the receiver
+    head: AtomicUsize,
+    /// Number of messages sent so far, only written by the sender
+    tail: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct RingBuffer {
+    mmap: MmapMut,
+    /// Base of the mapping, taken once so both ends can write through a shared reference
+    base: *mut u8,
+    capacity: usize,
+}
+
+impl RingBuffer {
+    fn new(capacity: usize, slot_size: usize) -> io::Result<Self> {
+        if capacity == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "ring capacity must be at least 1",
+            ));
+        }
+        // the mapping is zeroed, which is a valid `RingIndices` for an empty ring
+        let mut mmap = MmapOptions::new()
+            .len(size_of::<RingIndices>() + capacity * slot_size)
+            .map_anon()?;
+        let base = mmap.as_mut_ptr();
+        Ok(RingBuffer {
+            mmap,
+            base,
+            capacity,
+        })
+    }
+
+    fn indices(&self) -> &RingIndices {
+        // the mapping is page aligned
+        unsafe { &*(self.base as *const RingIndices) }
+    }
+
+    /// Pointer to the slot that message number `n` goes into. Slots aren't necessarily
+    /// aligned for the message type.
+    fn slot(&self, n: usize, slot_size: usize) -> *mut u8 {
+        let offset = size_of::<RingIndices>() + (n % self.capacity) * slot_size;
+        unsafe { self.base.add(offset) }
+    }
+}
+
+/// Receiving end of a bounded channel holding up to `capacity` messages at once.
+///
+/// Unlike `channel::Receiver`, the sender only has to wait if the ring is full, so a producer
+/// can run ahead of a slower consumer. Create it before forking and hand the `RingSender` to
+/// the producing process.
+#[derive(Debug)]
+pub struct RingReceiver<T> {
+    ring: RingBuffer,
+    phantom_data: PhantomData<T>,
+}
+
+#[derive(Debug)]
+pub struct RingSender<'a, T> {
+    ring: &'a RingBuffer,
+    phantom_data: PhantomData<T>,
+}
+
+impl<T: SharedSafe> RingReceiver<T> {
+    /// Creates a ring with room for `capacity` messages. Fails with
+    /// `ErrorKind::InvalidInput` for a capacity of 0.
+    pub fn new(capacity: usize) -> io::Result<Self> {
+        Ok(RingReceiver {
+            ring: RingBuffer::new(capacity, size_of::<T>())?,
+            phantom_data: PhantomData,
+        })
+    }
+
+    pub fn new_sender(&self) -> RingSender<'_, T> {
+        RingSender {
+            ring: &self.ring,
+            phantom_data: PhantomData,
+        }
+    }
+
+    /// Number of messages the ring can hold before `send` blocks
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity
+    }
+
+    /// Takes the oldest message out of the ring, blocking while it is empty
+    pub fn recv(&mut self) -> T {
+        self.recv_with_seq().0
+    }
+
+    /// Like `recv`, but also returns the sequence number of the message: the sender's tail
+    /// counter at the time it was sent, so 0 for the first message. Consecutive calls return
+    /// consecutive numbers, a jump by more than one means messages were skipped.
+    pub fn recv_with_seq(&mut self) -> (T, u64) {
+        let indices = self.ring.indices();
+        let head = indices.head.load(Ordering::Relaxed);
+        // Acquire pairs with the Release in `send`, so the slot is fully written
+        while indices.tail.load(Ordering::Acquire) == head {
+            std::thread::yield_now();
+        }
+        let t = unsafe { (self.ring.slot(head, size_of::<T>()) as *const T).read_unaligned() };
+        // Release so the sender can't overwrite the slot before we're done reading it
+        indices.head.store(head.wrapping_add(1), Ordering::Release);
+        (t, head as u64)
+    }
+}
+
+impl<'a, T: SharedSafe> RingSender<'a, T> {
+    /// Puts a message into the ring, blocking only while the ring is full
+    pub fn send(&mut self, data: T) {
+        let indices = self.ring.indices();
+        let tail = indices.tail.load(Ordering::Relaxed);
+        while tail.wrapping_sub(indices.head.load(Ordering::Acquire)) == self.ring.capacity {
+            std::thread::yield_now();
+        }
+        unsafe { (self.ring.slot(tail, size_of::<T>()) as *mut T).write_unaligned(data) };
+        indices.tail.store(tail.wrapping_add(1), Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use nix::sys::wait::waitpid;
+    use nix::unistd::{fork, ForkResult};
+
+    #[test]
+    fn fifo_with_slow_consumer() {
+        const CAPACITY: usize = 4;
+        const COUNT: u64 = CAPACITY as u64 + 5;
+
+        let mut receiver = RingReceiver::<u64>::new(CAPACITY).unwrap();
+        let mut sender = receiver.new_sender();
+
+        match fork() {
+            Ok(ForkResult::Parent { child, .. }) => {
+                for i in 0..COUNT {
+                    std::thread::sleep(Duration::from_millis(10));
+                    assert_eq!(receiver.recv(), i);
+                }
+                waitpid(child, None).unwrap();
+            }
+            Ok(ForkResult::Child) => {
+                for i in 0..COUNT {
+                    sender.send(i);
+                }
+                std::process::exit(0);
+            }
+            Err(e) => panic!("fork failed: {}", e),
+        }
+    }
+
+    #[test]
+    fn sequence_numbers_reveal_gaps() {
+        let mut receiver = RingReceiver::<u32>::new(8).unwrap();
+        let mut sender = receiver.new_sender();
+        for i in 0..6 {
+            sender.send(i * 10);
+        }
+
+        // under normal flow every message directly follows the previous one
+        let seqs: Vec<_> = (0..3)
+            .map(|i| {
+                let (value, seq) = receiver.recv_with_seq();
+                assert_eq!(value, i * 10);
+                seq
+            })
+            .collect();
+        assert_eq!(seqs, [0, 1, 2]);
+        // drop a message behind the receiver's back, like a producer lapping it would
+        receiver.ring.indices().head.fetch_add(1, Ordering::Release);
+        let (value, seq) = receiver.recv_with_seq();
+        assert_eq!(value, 40);
+        assert_eq!(seq - seqs[2], 2, "the gap went unnoticed");
+    }
+
+    #[test]
+    fn zero_capacity_is_rejected() {
+        let err = RingReceiver::<u64>::new(0).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+}
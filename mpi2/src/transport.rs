@@ -0,0 +1,136 @@
+//! What carries the bytes between two ranks of a `Communicator`.
+//!
+//! Forked ranks talk through `channel::Link`s in shared memory. `ThreadTransport` connects
+//! threads of a single process instead, so code written against a communicator - like the
+//! collectives - can be tested and debugged without forking.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
+
+use memmap::MmapOptions;
+
+use crate::barrier::SharedBarrier;
+use crate::channel::Link;
+use crate::Communicator;
+
+/// One direction of the connection between two ranks. Every `send` has to be matched by a
+/// `recv` of the same length on the other end.
+pub trait Transport {
+    /// Sends all of `data`, blocking as long as the other end isn't ready for it
+    fn send(&mut self, data: &[u8]);
+
+    /// Fills `out` with the next `out.len()` bytes sent, blocking until they are there
+    fn recv(&mut self, out: &mut [u8]);
+}
+
+impl Transport for Link {
+    fn send(&mut self, data: &[u8]) {
+        self.send_bytes(data);
+    }
+
+    fn recv(&mut self, out: &mut [u8]) {
+        self.recv_bytes(out);
+    }
+}
+
+/// A transport between threads of the same process. Clones share the same queue, one is
+/// handed to the sending thread and one to the receiving thread.
+///
+/// Unlike a `Link` it buffers any amount of data, so `send` never blocks.
+#[derive(Debug, Clone, Default)]
+pub struct ThreadTransport {
+    shared: Arc<(Mutex<VecDeque<u8>>, Condvar)>,
+}
+
+impl Transport for ThreadTransport {
+    fn send(&mut self, data: &[u8]) {
+        let (queue, arrived) = &*self.shared;
+        queue.lock().unwrap().extend(data);
+        arrived.notify_all();
+    }
+
+    fn recv(&mut self, out: &mut [u8]) {
+        let (queue, arrived) = &*self.shared;
+        let mut queue = arrived
+            .wait_while(queue.lock().unwrap(), |queue| queue.len() < out.len())
+            .unwrap();
+        let len = out.len();
+        for (byte, queued) in out.iter_mut().zip(queue.drain(..len)) {
+            *byte = queued;
+        }
+    }
+}
+
+impl Communicator<ThreadTransport> {
+    /// Creates a group of `n` ranks for threads of the calling process. Returns one
+    /// communicator per rank in rank order, each one is meant to be moved into its own thread.
+    ///
+    /// `abort` and `finalize` are about processes, don't use them on these.
+    pub fn threads(n: usize) -> io::Result<Vec<Self>> {
+        let epoch = Instant::now();
+        let links: Vec<ThreadTransport> = (0..n * n).map(|_| ThreadTransport::default()).collect();
+        let barrier = Arc::new(SharedBarrier::new(n)?);
+        (0..n)
+            .map(|rank| {
+                // all zero, `abort` skips ranks without a pid
+                let pids = MmapOptions::new()
+                    .len(n.max(1) * std::mem::size_of::<i32>())
+                    .map_anon()?;
+                Ok(Communicator::new(
+                    n,
+                    rank,
+                    epoch,
+                    links
+                        .iter()
+                        .enumerate()
+                        // messages to the own rank don't need a link
+                        .map(|(i, link)| {
+                            if i / n == i % n {
+                                None
+                            } else {
+                                Some(RefCell::new(link.clone()))
+                            }
+                        })
+                        .collect(),
+                    barrier.clone(),
+                    pids,
+                    Vec::new(),
+                ))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::collective::{barrier, broadcast};
+
+    #[test]
+    fn broadcast_in_threads() {
+        let data = (42u64, -1.5f64, [1i32, 2, 3]);
+        let threads: Vec<_> = Communicator::threads(5)
+            .unwrap()
+            .into_iter()
+            .map(|comm| {
+                std::thread::spawn(move || {
+                    let mut value = if comm.rank() == 3 {
+                        data
+                    } else {
+                        Default::default()
+                    };
+                    broadcast(&comm, 3, &mut value);
+                    barrier(&comm);
+                    value
+                })
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), data);
+        }
+    }
+}
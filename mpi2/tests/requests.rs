@@ -0,0 +1,5 @@
+#[test]
+fn overlapping_requests_are_rejected() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/requests/*.rs");
+}
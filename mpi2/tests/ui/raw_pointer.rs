@@ -0,0 +1,14 @@
+use mpi2::channel::Receiver;
+
+#[derive(Clone, Copy)]
+struct Message {
+    data: *const u8,
+}
+
+fn main() {
+    let mut receiver = Receiver::<Message>::new().unwrap();
+    let mut sender = receiver.new_sender();
+    sender.send(Message {
+        data: "hello".as_ptr(),
+    });
+}
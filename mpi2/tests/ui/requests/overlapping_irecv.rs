@@ -0,0 +1,9 @@
+use mpi2::channel::Receiver;
+
+fn main() {
+    let mut receiver = Receiver::<u32>::new().unwrap();
+    let first = receiver.irecv();
+    let second = receiver.irecv();
+    first.wait().unwrap();
+    second.wait().unwrap();
+}
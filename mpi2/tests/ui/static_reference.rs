@@ -0,0 +1,12 @@
+use mpi2::channel::Receiver;
+
+#[derive(Clone, Copy)]
+struct Message {
+    text: &'static str,
+}
+
+fn main() {
+    let mut receiver = Receiver::<Message>::new().unwrap();
+    let mut sender = receiver.new_sender();
+    sender.send(Message { text: "hello" });
+}